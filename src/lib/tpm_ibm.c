@@ -143,6 +143,7 @@ struct _uta_context_v1_t
 /*******************************************************************************
  * Private function prototypes
  ******************************************************************************/
+static void tpm_zeroize(void *buffer, size_t len_buffer);
 static uint32_t tpm_start_hmac_session(const uta_context_v1_t *tpm_context);
 static uint32_t tpm_flush_context(const uta_context_v1_t *tpm_context,
         uint32_t handle_number);
@@ -333,6 +334,7 @@ uta_rc tpm_derive_key(const uta_context_v1_t *tpm_context, uint8_t *key,
         return UTA_TA_ERROR;
     }
     memcpy(key,key_buffer,len_key);
+    tpm_zeroize(key_buffer, sizeof(key_buffer));
 
     return UTA_SUCCESS;
 }
@@ -486,6 +488,22 @@ uta_rc tpm_self_test(const uta_context_v1_t *tpm_context)
 /*******************************************************************************
  * Private function bodies
  ******************************************************************************/ 
+/**
+ * @brief Overwrites a buffer containing key material with zeros. The volatile
+ *      access prevents the compiler from removing the writes.
+ * @param[in,out] buffer Pointer to the buffer.
+ * @param[in] len_buffer Length of the buffer in bytes.
+ */
+static void tpm_zeroize(void *buffer, size_t len_buffer)
+{
+    volatile uint8_t *p = buffer;
+
+    while(len_buffer--)
+    {
+        *p++ = 0;
+    }
+}
+
 /**
  * @brief Starts an HMAC session with the TPM.
  * @param[in,out] tpm_context Pointer to the internal context struct.
@@ -620,6 +638,7 @@ static uint32_t tpm_calc_hmac(const uta_context_v1_t *tpm_context,
     {
         // Copy HMAC to output buffer
         memcpy(hmac, out.outHMAC.t.buffer, out.outHMAC.t.size);
+        tpm_zeroize(out.outHMAC.t.buffer, out.outHMAC.t.size);
     }
 
     return rc;
@@ -55,6 +55,7 @@
 #include <stdlib.h>
 #include <string.h>
 #include <stdint.h>
+#include <errno.h>
 
 #include <config.h>
 #include <tpm_tcg.h>
@@ -84,6 +85,10 @@ struct _uta_context_v1_t
 /*******************************************************************************
  * Private function prototypes
  ******************************************************************************/
+static void tpm_zeroize(void *buffer, size_t len_buffer);
+#ifndef SIM
+static void tpm_print_device_hint(const char *device);
+#endif
         
 /*******************************************************************************
  * Public function bodies
@@ -147,6 +152,7 @@ uta_rc tpm_open(const uta_context_v1_t *tpm_context)
     ret = Tss2_Tcti_Device_Init(tpm_context_w->tcti_ctx, &size, conf_str);
     if(ret != TSS2_RC_SUCCESS){
         printf("Tss2_Tcti_Device_Init failed\n");
+        tpm_print_device_hint(conf_str);
         return UTA_TA_ERROR;
     }
 
@@ -304,10 +310,13 @@ uta_rc tpm_derive_key(const uta_context_v1_t *tpm_context, uint8_t *key,
     
     if(outHMAC->size < len_key){
         printf("Output not long enough\n");
+        tpm_zeroize(outHMAC->buffer, outHMAC->size);
+        free(outHMAC);
         return UTA_TA_ERROR;
     }
     
 	memcpy(key,outHMAC->buffer,len_key);
+    tpm_zeroize(outHMAC->buffer, outHMAC->size);
     free(outHMAC);
 
     return UTA_SUCCESS;
@@ -514,3 +523,54 @@ uta_rc tpm_self_test(const uta_context_v1_t *tpm_context)
 	
 	return UTA_SUCCESS;
 }
+
+/*******************************************************************************
+ * Private function bodies
+ ******************************************************************************/
+/**
+ * @brief Overwrites a buffer containing key material with zeros. The volatile
+ *      access prevents the compiler from removing the writes.
+ * @param[in,out] buffer Pointer to the buffer.
+ * @param[in] len_buffer Length of the buffer in bytes.
+ */
+static void tpm_zeroize(void *buffer, size_t len_buffer)
+{
+    volatile uint8_t *p = buffer;
+
+    while(len_buffer--)
+    {
+        *p++ = 0;
+    }
+}
+
+#ifndef SIM
+/**
+ * @brief Prints a hint on the reason why the TPM device cannot be opened.
+ * @param[in] device Path of the TPM device file.
+ */
+static void tpm_print_device_hint(const char *device)
+{
+    int fd;
+
+    fd = open(device, O_RDWR);
+    if(fd >= 0){
+        (void)close(fd);
+        return;
+    }
+
+    switch(errno){
+    case ENOENT:
+        printf("Hint: %s does not exist, check that the TPM driver (e.g. tpm_tis or tpm_crb) is loaded\n", device);
+        break;
+    case EACCES:
+        printf("Hint: No read/write access to %s, add the user to the group owning the device (e.g. tss)\n", device);
+        break;
+    case EBUSY:
+        printf("Hint: %s is already in use by another process (e.g. a resource manager or another UTA context)\n", device);
+        break;
+    default:
+        printf("Hint: %s cannot be opened: %s\n", device, strerror(errno));
+        break;
+    }
+}
+#endif
@@ -19,11 +19,99 @@
 #include <config.h>
 #include <stdio.h>
 #include <stdint.h>
+#include <stdlib.h>
+#include <string.h>
+#include <pthread.h>
 #include <uta.h>
 #include <tpm_ibm.h>
 #include <uta_sim.h>
 #include <tpm_tcg.h>
 
+#if defined(OPERATIONAL_GATING) && defined(RECORDING)
+#error "The recording cannot be combined with the operational gating mode"
+#endif
+
+#ifdef OPERATIONAL_GATING
+/*******************************************************************************
+ * Data types
+ ******************************************************************************/
+typedef enum {GATE_UNTESTED, GATE_PASSED, GATE_ERROR} gate_state_t;
+
+/*******************************************************************************
+ * Defines
+ ******************************************************************************/
+/* Parameters of the known-answer test of derive_key */
+#define GATE_KAT_KEY_SLOT 0
+#define GATE_KAT_DV       "UTA_KAT!"
+#define GATE_KAT_LEN      32
+
+#ifndef GATING_KAT
+#ifdef HW_BACKEND_UTA_SIM
+/* Expected output of the known-answer test with KEY_SLOT_0 of the simulator */
+#define GATING_KAT        "51b380e932df0355f0565ed42963871b12282fadf58b35042c1494a7213fa102"
+#else
+#error "GATING_KAT has to be defined for the operational gating mode"
+#endif
+#endif
+
+/*******************************************************************************
+ * Static data declaration
+ ******************************************************************************/
+/* Function pointers of the trust anchor, which is gated */
+static uta_api_v1_t gated_uta;
+static gate_state_t gate_state = GATE_UNTESTED;
+static pthread_mutex_t gate_mutex = PTHREAD_MUTEX_INITIALIZER;
+
+/*******************************************************************************
+ * Private function prototypes
+ ******************************************************************************/
+static uta_rc uta_gate_init(uta_api_v1_t *uta);
+static uta_rc gate_power_on_self_test(void);
+static uta_rc gate_hex2bytes(const char *hex, uint8_t *buffer,
+        size_t len_buffer);
+static uta_rc gate_check(void);
+static uta_rc gate_open(const uta_context_v1_t *uta_context);
+static uta_rc gate_close(const uta_context_v1_t *uta_context);
+static uta_rc gate_derive_key(const uta_context_v1_t *uta_context, uint8_t *key,
+        size_t len_key, const uint8_t *dv, size_t len_dv, uint8_t key_slot);
+static uta_rc gate_get_random(const uta_context_v1_t *uta_context,
+        uint8_t *random, size_t len_random);
+static uta_rc gate_get_device_uuid(const uta_context_v1_t *uta_context,
+        uint8_t *uuid);
+static uta_rc gate_self_test(const uta_context_v1_t *uta_context);
+#endif
+
+#ifdef RECORDING
+/*******************************************************************************
+ * Defines
+ ******************************************************************************/
+/* Environment variable containing the path of the cassette file */
+#define ENV_RECORD        "UTA_RECORD"
+
+/*******************************************************************************
+ * Static data declaration
+ ******************************************************************************/
+/* Function pointers of the trust anchor, which is recorded */
+static uta_api_v1_t recorded_uta;
+static FILE *cassette = NULL;
+static pthread_mutex_t cassette_mutex = PTHREAD_MUTEX_INITIALIZER;
+
+/*******************************************************************************
+ * Private function prototypes
+ ******************************************************************************/
+static uta_rc uta_record_init(uta_api_v1_t *uta);
+static void rec_write_hex(const uint8_t *buffer, size_t len_buffer);
+static uta_rc rec_open(const uta_context_v1_t *uta_context);
+static uta_rc rec_close(const uta_context_v1_t *uta_context);
+static uta_rc rec_derive_key(const uta_context_v1_t *uta_context, uint8_t *key,
+        size_t len_key, const uint8_t *dv, size_t len_dv, uint8_t key_slot);
+static uta_rc rec_get_random(const uta_context_v1_t *uta_context,
+        uint8_t *random, size_t len_random);
+static uta_rc rec_get_device_uuid(const uta_context_v1_t *uta_context,
+        uint8_t *uuid);
+static uta_rc rec_self_test(const uta_context_v1_t *uta_context);
+#endif
+
 /*******************************************************************************
  * Public function bodies
  ******************************************************************************/
@@ -107,6 +195,461 @@ uta_rc uta_init_v1(uta_api_v1_t *uta)
 // Hardware independent function
     uta->get_version=&uta_get_version;
     uta->len_key_max=&uta_len_key_max;
-    
+
+#if defined(OPERATIONAL_GATING)
+    return uta_gate_init(uta);
+#elif defined(RECORDING)
+    return uta_record_init(uta);
+#else
+    return UTA_SUCCESS;
+#endif
+}
+
+#ifdef OPERATIONAL_GATING
+/*******************************************************************************
+ * Private function bodies
+ ******************************************************************************/
+/**
+ * @brief Replaces the trust anchor functions by the gated functions. The first
+ *      call runs the power-on self-test. If it fails, the library latches
+ *      into an error state and all gated functions return UTA_TA_ERROR until
+ *      the process terminates.
+ * @param[in,out] uta Struct with the v1 function pointers.
+ * @return UTA return code, UTA_TA_ERROR if the power-on self-test failed.
+ */
+static uta_rc uta_gate_init(uta_api_v1_t *uta)
+{
+    uta_rc rc = UTA_SUCCESS;
+
+    (void)pthread_mutex_lock(&gate_mutex);
+    gated_uta = *uta;
+    if(gate_state == GATE_UNTESTED)
+    {
+        gate_state = (gate_power_on_self_test() == UTA_SUCCESS) ?
+            GATE_PASSED : GATE_ERROR;
+    }
+    if(gate_state != GATE_PASSED)
+    {
+        rc = UTA_TA_ERROR;
+    }
+    (void)pthread_mutex_unlock(&gate_mutex);
+
+    /* The function pointers are replaced in any case, so that a caller
+     * ignoring the return code cannot bypass the gating */
+    uta->open=&gate_open;
+    uta->close=&gate_close;
+    uta->derive_key=&gate_derive_key;
+    uta->get_random=&gate_get_random;
+    uta->get_device_uuid=&gate_get_device_uuid;
+    uta->self_test=&gate_self_test;
+
+    return rc;
+}
+
+/**
+ * @brief Runs the power-on self-test with a separate context: self_test of
+ *      the trust anchor and a known-answer test of derive_key. The gate_mutex
+ *      has to be locked by the caller.
+ * @return UTA return code.
+ */
+static uta_rc gate_power_on_self_test(void)
+{
+    uta_context_v1_t *uta_context;
+    uint8_t expected[GATE_KAT_LEN];
+    uint8_t key[GATE_KAT_LEN];
+    uta_rc rc;
+
+    if(gate_hex2bytes(GATING_KAT, expected, GATE_KAT_LEN) != UTA_SUCCESS)
+    {
+        return UTA_TA_ERROR;
+    }
+
+    uta_context = malloc(gated_uta.context_v1_size());
+    if(uta_context == NULL)
+    {
+        return UTA_TA_ERROR;
+    }
+
+    rc = gated_uta.open(uta_context);
+    if(rc != UTA_SUCCESS)
+    {
+        free(uta_context);
+        return rc;
+    }
+
+    rc = gated_uta.self_test(uta_context);
+    if(rc == UTA_SUCCESS)
+    {
+        rc = gated_uta.derive_key(uta_context, key, GATE_KAT_LEN,
+            (const uint8_t *)GATE_KAT_DV, UTA_LEN_DV_V1, GATE_KAT_KEY_SLOT);
+    }
+    if((rc == UTA_SUCCESS) && (memcmp(key, expected, GATE_KAT_LEN) != 0))
+    {
+        rc = UTA_TA_ERROR;
+    }
+    if(gated_uta.close(uta_context) != UTA_SUCCESS)
+    {
+        rc = UTA_TA_ERROR;
+    }
+
+    /* The output is not wiped: it is the known answer, which is part of the
+     * binary, and the derivation value GATE_KAT_DV is reserved for the
+     * known-answer test. */
+    free(uta_context);
+
+    return rc;
+}
+
+/**
+ * @brief Converts a hex string into a buffer.
+ * @param[in] hex Hex string with exactly 2*len_buffer characters.
+ * @param[out] buffer Output buffer.
+ * @param[in] len_buffer Length of the output buffer in bytes.
+ * @return UTA return code.
+ */
+static uta_rc gate_hex2bytes(const char *hex, uint8_t *buffer,
+    size_t len_buffer)
+{
+    unsigned int value;
+
+    if(strlen(hex) != 2*len_buffer)
+    {
+        return UTA_TA_ERROR;
+    }
+
+    for(size_t i=0; i<len_buffer; i++)
+    {
+        if(sscanf(&hex[2*i], "%2x", &value) != 1)
+        {
+            return UTA_TA_ERROR;
+        }
+        buffer[i] = (uint8_t)value;
+    }
+
+    return UTA_SUCCESS;
+}
+
+/**
+ * @brief Checks if the power-on self-test has passed.
+ * @return UTA_SUCCESS if the power-on self-test has passed, UTA_TA_ERROR
+ *      otherwise.
+ */
+static uta_rc gate_check(void)
+{
+    gate_state_t state;
+
+    (void)pthread_mutex_lock(&gate_mutex);
+    state = gate_state;
+    (void)pthread_mutex_unlock(&gate_mutex);
+
+    return (state == GATE_PASSED) ? UTA_SUCCESS : UTA_TA_ERROR;
+}
+
+/**
+ * @brief Calls open of the gated trust anchor, if the power-on self-test has
+ *      passed.
+ * @param[in,out] uta_context Pointer to the internal context struct.
+ * @return UTA return code.
+ */
+static uta_rc gate_open(const uta_context_v1_t *uta_context)
+{
+    if(gate_check() != UTA_SUCCESS)
+    {
+        return UTA_TA_ERROR;
+    }
+
+    return gated_uta.open(uta_context);
+}
+
+/**
+ * @brief Calls close of the gated trust anchor. The close is not gated, so
+ *      that the resources of an open context can always be released.
+ * @param[in,out] uta_context Pointer to the internal context struct.
+ * @return UTA return code.
+ */
+static uta_rc gate_close(const uta_context_v1_t *uta_context)
+{
+    return gated_uta.close(uta_context);
+}
+
+/**
+ * @brief Calls derive_key of the gated trust anchor, if the power-on
+ *      self-test has passed.
+ * @param[in,out] uta_context Pointer to the internal context struct.
+ * @param[out] key Pointer to the buffer where the derived key is written to.
+ * @param[in] len_key Number of bytes, which should be written to key.
+ * @param[in] dv Pointer to the derivation value.
+ * @param[in] len_dv Length of the derivation value in bytes.
+ * @param[in] key_slot Key slot used for the derivation.
+ * @return UTA return code.
+ */
+static uta_rc gate_derive_key(const uta_context_v1_t *uta_context, uint8_t *key,
+    size_t len_key, const uint8_t *dv, size_t len_dv, uint8_t key_slot)
+{
+    if(gate_check() != UTA_SUCCESS)
+    {
+        return UTA_TA_ERROR;
+    }
+
+    return gated_uta.derive_key(uta_context, key, len_key, dv, len_dv,
+        key_slot);
+}
+
+/**
+ * @brief Calls get_random of the gated trust anchor, if the power-on
+ *      self-test has passed.
+ * @param[in,out] uta_context Pointer to the internal context struct.
+ * @param[out] random Pointer to the buffer for the random bytes.
+ * @param[in] len_random Number of random bytes.
+ * @return UTA return code.
+ */
+static uta_rc gate_get_random(const uta_context_v1_t *uta_context,
+    uint8_t *random, size_t len_random)
+{
+    if(gate_check() != UTA_SUCCESS)
+    {
+        return UTA_TA_ERROR;
+    }
+
+    return gated_uta.get_random(uta_context, random, len_random);
+}
+
+/**
+ * @brief Calls get_device_uuid of the gated trust anchor, if the power-on
+ *      self-test has passed.
+ * @param[in,out] uta_context Pointer to the internal context struct.
+ * @param[out] uuid Pointer to the 16 Byte buffer for the UUID.
+ * @return UTA return code.
+ */
+static uta_rc gate_get_device_uuid(const uta_context_v1_t *uta_context,
+    uint8_t *uuid)
+{
+    if(gate_check() != UTA_SUCCESS)
+    {
+        return UTA_TA_ERROR;
+    }
+
+    return gated_uta.get_device_uuid(uta_context, uuid);
+}
+
+/**
+ * @brief Calls self_test of the gated trust anchor, if the power-on self-test
+ *      has passed.
+ * @param[in,out] uta_context Pointer to the internal context struct.
+ * @return UTA return code.
+ */
+static uta_rc gate_self_test(const uta_context_v1_t *uta_context)
+{
+    if(gate_check() != UTA_SUCCESS)
+    {
+        return UTA_TA_ERROR;
+    }
+
+    return gated_uta.self_test(uta_context);
+}
+#endif
+
+#ifdef RECORDING
+/*******************************************************************************
+ * Private function bodies
+ ******************************************************************************/
+/**
+ * @brief Replaces the trust anchor functions by the recording functions, if
+ *      the environment variable UTA_RECORD contains the path of a cassette
+ *      file. Each call is appended as one line to the cassette:
+ *      <operation>[ <parameters>] <return code>[ <output as hex>]
+ * @param[in,out] uta Struct with the v1 function pointers.
+ * @return UTA return code.
+ */
+static uta_rc uta_record_init(uta_api_v1_t *uta)
+{
+    const char *path;
+
+    path = getenv(ENV_RECORD);
+    if(path == NULL)
+    {
+        return UTA_SUCCESS;
+    }
+
+    (void)pthread_mutex_lock(&cassette_mutex);
+    if(cassette == NULL)
+    {
+        cassette = fopen(path, "a");
+        if(cassette == NULL)
+        {
+            (void)pthread_mutex_unlock(&cassette_mutex);
+            return UTA_TA_ERROR;
+        }
+    }
+    recorded_uta = *uta;
+    (void)pthread_mutex_unlock(&cassette_mutex);
+
+    uta->open=&rec_open;
+    uta->close=&rec_close;
+    uta->derive_key=&rec_derive_key;
+    uta->get_random=&rec_get_random;
+    uta->get_device_uuid=&rec_get_device_uuid;
+    uta->self_test=&rec_self_test;
+
     return UTA_SUCCESS;
 }
+
+/**
+ * @brief Writes a buffer as hex string to the cassette. An empty buffer is
+ *      written as '-'. The cassette_mutex has to be locked by the caller.
+ * @param[in] buffer Buffer to be written.
+ * @param[in] len_buffer Length of the buffer in bytes.
+ */
+static void rec_write_hex(const uint8_t *buffer, size_t len_buffer)
+{
+    if(len_buffer == 0)
+    {
+        (void)fputc('-', cassette);
+        return;
+    }
+
+    for(size_t i=0; i<len_buffer; i++)
+    {
+        (void)fprintf(cassette, "%02x", (unsigned int)buffer[i]);
+    }
+}
+
+/**
+ * @brief Calls open of the recorded trust anchor and records the call.
+ * @param[in,out] uta_context Pointer to the internal context struct.
+ * @return UTA return code of the recorded trust anchor.
+ */
+static uta_rc rec_open(const uta_context_v1_t *uta_context)
+{
+    uta_rc rc;
+
+    rc = recorded_uta.open(uta_context);
+
+    (void)pthread_mutex_lock(&cassette_mutex);
+    (void)fprintf(cassette, "open 0x%02x\n", (unsigned int)rc);
+    (void)fflush(cassette);
+    (void)pthread_mutex_unlock(&cassette_mutex);
+
+    return rc;
+}
+
+/**
+ * @brief Calls close of the recorded trust anchor and records the call.
+ * @param[in,out] uta_context Pointer to the internal context struct.
+ * @return UTA return code of the recorded trust anchor.
+ */
+static uta_rc rec_close(const uta_context_v1_t *uta_context)
+{
+    uta_rc rc;
+
+    rc = recorded_uta.close(uta_context);
+
+    (void)pthread_mutex_lock(&cassette_mutex);
+    (void)fprintf(cassette, "close 0x%02x\n", (unsigned int)rc);
+    (void)fflush(cassette);
+    (void)pthread_mutex_unlock(&cassette_mutex);
+
+    return rc;
+}
+
+/**
+ * @brief Calls derive_key of the recorded trust anchor and records the call
+ *      including the derived key.
+ * @param[in,out] uta_context Pointer to the internal context struct.
+ * @param[out] key Pointer to the buffer where the derived key is written to.
+ * @param[in] len_key Number of bytes, which should be written to key.
+ * @param[in] dv Pointer to the derivation value.
+ * @param[in] len_dv Length of the derivation value in bytes.
+ * @param[in] key_slot Key slot used for the derivation.
+ * @return UTA return code of the recorded trust anchor.
+ */
+static uta_rc rec_derive_key(const uta_context_v1_t *uta_context, uint8_t *key,
+    size_t len_key, const uint8_t *dv, size_t len_dv, uint8_t key_slot)
+{
+    uta_rc rc;
+
+    rc = recorded_uta.derive_key(uta_context, key, len_key, dv, len_dv,
+        key_slot);
+
+    (void)pthread_mutex_lock(&cassette_mutex);
+    (void)fprintf(cassette, "derive_key %u %zu ", (unsigned int)key_slot,
+        len_key);
+    rec_write_hex(dv, len_dv);
+    (void)fprintf(cassette, " 0x%02x ", (unsigned int)rc);
+    rec_write_hex(key, (rc == UTA_SUCCESS) ? len_key : 0);
+    (void)fputc('\n', cassette);
+    (void)fflush(cassette);
+    (void)pthread_mutex_unlock(&cassette_mutex);
+
+    return rc;
+}
+
+/**
+ * @brief Calls get_random of the recorded trust anchor and records the call
+ *      including the random bytes.
+ * @param[in,out] uta_context Pointer to the internal context struct.
+ * @param[out] random Pointer to the buffer for the random bytes.
+ * @param[in] len_random Number of random bytes.
+ * @return UTA return code of the recorded trust anchor.
+ */
+static uta_rc rec_get_random(const uta_context_v1_t *uta_context,
+    uint8_t *random, size_t len_random)
+{
+    uta_rc rc;
+
+    rc = recorded_uta.get_random(uta_context, random, len_random);
+
+    (void)pthread_mutex_lock(&cassette_mutex);
+    (void)fprintf(cassette, "get_random %zu 0x%02x ", len_random,
+        (unsigned int)rc);
+    rec_write_hex(random, (rc == UTA_SUCCESS) ? len_random : 0);
+    (void)fputc('\n', cassette);
+    (void)fflush(cassette);
+    (void)pthread_mutex_unlock(&cassette_mutex);
+
+    return rc;
+}
+
+/**
+ * @brief Calls get_device_uuid of the recorded trust anchor and records the
+ *      call including the UUID.
+ * @param[in,out] uta_context Pointer to the internal context struct.
+ * @param[out] uuid Pointer to the 16 Byte buffer for the UUID.
+ * @return UTA return code of the recorded trust anchor.
+ */
+static uta_rc rec_get_device_uuid(const uta_context_v1_t *uta_context,
+    uint8_t *uuid)
+{
+    uta_rc rc;
+
+    rc = recorded_uta.get_device_uuid(uta_context, uuid);
+
+    (void)pthread_mutex_lock(&cassette_mutex);
+    (void)fprintf(cassette, "get_device_uuid 0x%02x ", (unsigned int)rc);
+    rec_write_hex(uuid, (rc == UTA_SUCCESS) ? 16 : 0);
+    (void)fputc('\n', cassette);
+    (void)fflush(cassette);
+    (void)pthread_mutex_unlock(&cassette_mutex);
+
+    return rc;
+}
+
+/**
+ * @brief Calls self_test of the recorded trust anchor and records the call.
+ * @param[in,out] uta_context Pointer to the internal context struct.
+ * @return UTA return code of the recorded trust anchor.
+ */
+static uta_rc rec_self_test(const uta_context_v1_t *uta_context)
+{
+    uta_rc rc;
+
+    rc = recorded_uta.self_test(uta_context);
+
+    (void)pthread_mutex_lock(&cassette_mutex);
+    (void)fprintf(cassette, "self_test 0x%02x\n", (unsigned int)rc);
+    (void)fflush(cassette);
+    (void)pthread_mutex_unlock(&cassette_mutex);
+
+    return rc;
+}
+#endif
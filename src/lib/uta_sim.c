@@ -23,10 +23,15 @@
 #include <stdlib.h>
 #include <string.h>
 #include <time.h>
+#include <errno.h>
+#include <limits.h>
+#include <ctype.h>
+#include <pthread.h>
 
 #include <config.h>
 #include <uta_sim.h>
 #include <mbedtls/md.h>
+#include <mbedtls/platform_util.h>
 
 /*******************************************************************************
  * Data types
@@ -36,6 +41,14 @@ struct _uta_context_v1_t
     
 };
 
+/* Faults, which can be injected into the simulated trust anchor calls */
+typedef enum
+{
+    SIM_FAULT_NONE,    /* Call is executed normally */
+    SIM_FAULT_FAIL,    /* Call returns the configured error code */
+    SIM_FAULT_CORRUPT  /* Call succeeds, but the output buffer is corrupted */
+} sim_fault_t;
+
 /*******************************************************************************
  * Defines
  ******************************************************************************/
@@ -43,11 +56,62 @@ struct _uta_context_v1_t
 #define DERIV_VAL_LEN     8
 #define USED_KEY_SLOTS    2
 
+/* Environment variables controlling the fault injection of the simulator */
+#define ENV_FAIL_CALL     "UTA_SIM_FAIL_CALL"
+#define ENV_FAIL_COUNT    "UTA_SIM_FAIL_COUNT"
+#define ENV_FAIL_RC       "UTA_SIM_FAIL_RC"
+#define ENV_FAIL_MODE     "UTA_SIM_FAIL_MODE"
+
+/* Environment variables controlling the latency injection of the simulator */
+#define ENV_LATENCY       "UTA_SIM_LATENCY_MS"
+#define ENV_JITTER        "UTA_SIM_JITTER_MS"
+#define MAX_LATENCY_MS    60000 /* Upper limit of latency and jitter */
+
+/* Environment variable to seed the PRNG of get_random deterministically */
+#define ENV_SEED          "UTA_SIM_SEED"
+
+/* Environment variable containing the path of a cassette to be replayed */
+#define ENV_REPLAY        "UTA_SIM_REPLAY"
+
 /*******************************************************************************
  * Constants
  ******************************************************************************/
 const uint8_t KEY_SLOTS[USED_KEY_SLOTS][KEY_LEN]={KEY_SLOT_0,KEY_SLOT_1};
 
+/*******************************************************************************
+ * Static data declaration
+ ******************************************************************************/
+/* Number of trust anchor calls since the library has been loaded */
+static unsigned long call_counter = 0;
+static pthread_mutex_t call_counter_mutex = PTHREAD_MUTEX_INITIALIZER;
+/* Separate seed for the jitter, so the output of get_random is not affected */
+static unsigned int jitter_seed = 0;
+static pthread_mutex_t jitter_mutex = PTHREAD_MUTEX_INITIALIZER;
+/* State of the PRNG of get_random, if it is seeded with UTA_SIM_SEED */
+static unsigned int random_state = 0;
+static int random_seeded = 0;
+static pthread_mutex_t random_mutex = PTHREAD_MUTEX_INITIALIZER;
+/* Cassette, which is replayed if UTA_SIM_REPLAY is set */
+static FILE *replay_file = NULL;
+static pthread_mutex_t replay_mutex = PTHREAD_MUTEX_INITIALIZER;
+
+/*******************************************************************************
+ * Private function prototypes
+ ******************************************************************************/
+static sim_fault_t sim_next_fault(uta_rc *rc);
+static void sim_corrupt_buffer(uint8_t *buffer, size_t len_buffer);
+static uta_rc sim_getenv_ulong(const char *name, unsigned long max,
+        unsigned long *value);
+static uta_rc sim_replay(const char *operation, const char *params,
+        uint8_t *output, size_t len_output);
+static uta_rc sim_replay_parse(const char *line, const char *operation,
+        const char *params, uint8_t *output, size_t len_output, uta_rc *rc);
+static uta_rc sim_replay_derive_key(uint8_t *key, size_t len_key,
+        const uint8_t *dv, size_t len_dv, uint8_t key_slot);
+static uta_rc sim_hex2bytes(const char *hex, uint8_t *buffer,
+        size_t len_buffer);
+static uta_rc sim_inject_latency(void);
+
 /*******************************************************************************
  * Public function bodies
  ******************************************************************************/
@@ -61,12 +125,49 @@ size_t sim_context_v1_size(void)
 }
  
 /**
- * @brief Opens a simulation session.
+ * @brief Opens a simulation session. If UTA_SIM_SEED is set, the PRNG of
+ *      get_random is seeded once per process with the given value, so the
+ *      random numbers are reproducible over all sessions. A malformed seed
+ *      lets the call fail with UTA_TA_ERROR.
  * @param[in,out] sim_context Pointer to the internal context struct.
  * @return UTA return code.
  */
 uta_rc sim_open(const uta_context_v1_t *sim_context)
 {
+    const char *env;
+    unsigned long seed;
+    uta_rc rc;
+
+    rc = sim_inject_latency();
+    if(rc != UTA_SUCCESS)
+    {
+        return rc;
+    }
+
+    if(getenv(ENV_REPLAY) != NULL)
+    {
+        return sim_replay("open", NULL, NULL, 0);
+    }
+
+    env = getenv(ENV_SEED);
+    if(env != NULL)
+    {
+        if(sim_getenv_ulong(ENV_SEED, UINT_MAX, &seed) != UTA_SUCCESS)
+        {
+            return UTA_TA_ERROR;
+        }
+
+        (void)pthread_mutex_lock(&random_mutex);
+        if(!random_seeded)
+        {
+            random_state = (unsigned int)seed;
+            random_seeded = 1;
+        }
+        (void)pthread_mutex_unlock(&random_mutex);
+
+        return UTA_SUCCESS;
+    }
+
     /* Initialize the PRNG */
     time_t t;
     srand((unsigned) time(&t));
@@ -81,6 +182,19 @@ uta_rc sim_open(const uta_context_v1_t *sim_context)
  */
 uta_rc sim_close(const uta_context_v1_t *sim_context)
 {
+    uta_rc rc;
+
+    rc = sim_inject_latency();
+    if(rc != UTA_SUCCESS)
+    {
+        return rc;
+    }
+
+    if(getenv(ENV_REPLAY) != NULL)
+    {
+        return sim_replay("close", NULL, NULL, 0);
+    }
+
     return UTA_SUCCESS;
 }
 
@@ -100,9 +214,33 @@ uta_rc sim_derive_key(const uta_context_v1_t *sim_context, uint8_t *key,
     size_t len_key, const uint8_t *dv,size_t len_dv, uint8_t key_slot)
 {
     uint8_t key_buffer[KEY_LEN];
+    uta_rc rc;
+    sim_fault_t fault;
     const mbedtls_md_info_t *sha256_hmac =
         mbedtls_md_info_from_type(MBEDTLS_MD_SHA256);
 
+    rc = sim_inject_latency();
+    if(rc != UTA_SUCCESS)
+    {
+        return rc;
+    }
+
+    fault = sim_next_fault(&rc);
+    if(fault == SIM_FAULT_FAIL)
+    {
+        return rc;
+    }
+
+    if(getenv(ENV_REPLAY) != NULL)
+    {
+        rc = sim_replay_derive_key(key, len_key, dv, len_dv, key_slot);
+        if((rc == UTA_SUCCESS) && (fault == SIM_FAULT_CORRUPT))
+        {
+            sim_corrupt_buffer(key, len_key);
+        }
+        return rc;
+    }
+
     if(key_slot > (USED_KEY_SLOTS-1))
     {
         return UTA_INVALID_KEY_SLOT;
@@ -121,12 +259,19 @@ uta_rc sim_derive_key(const uta_context_v1_t *sim_context, uint8_t *key,
     mbedtls_md_hmac(sha256_hmac, KEY_SLOTS[key_slot], KEY_LEN,
         dv, len_dv, key_buffer);
     memcpy(key,key_buffer,len_key);
+    mbedtls_platform_zeroize(key_buffer, KEY_LEN);
+
+    if(fault == SIM_FAULT_CORRUPT)
+    {
+        sim_corrupt_buffer(key, len_key);
+    }
 
     return UTA_SUCCESS;
 }
 
 /**
- * @brief Gets random numbers using the rand() function.
+ * @brief Gets random numbers using the rand() function, or rand_r() with a
+ *      private state if the PRNG has been seeded with UTA_SIM_SEED.
  * @param[in,out] sim_context Pointer to the internal context struct.
  * @param[out] random Pointer to the buffer where the random numbers are written
  *      to.
@@ -136,9 +281,50 @@ uta_rc sim_derive_key(const uta_context_v1_t *sim_context, uint8_t *key,
 uta_rc sim_get_random(const uta_context_v1_t *sim_context, uint8_t *random,
     size_t len_random)
 {
+    uta_rc rc;
+    sim_fault_t fault;
+    char params[24];
+
+    rc = sim_inject_latency();
+    if(rc != UTA_SUCCESS)
+    {
+        return rc;
+    }
+
+    fault = sim_next_fault(&rc);
+    if(fault == SIM_FAULT_FAIL)
+    {
+        return rc;
+    }
+
+    if(getenv(ENV_REPLAY) != NULL)
+    {
+        (void)snprintf(params, sizeof(params), "%zu", len_random);
+        rc = sim_replay("get_random", params, random, len_random);
+        if((rc == UTA_SUCCESS) && (fault == SIM_FAULT_CORRUPT))
+        {
+            sim_corrupt_buffer(random, len_random);
+        }
+        return rc;
+    }
+
+    (void)pthread_mutex_lock(&random_mutex);
     for(int i=0; i<len_random; i++)
     {
-        random[i]=rand() % 256;
+        if(random_seeded)
+        {
+            random[i]=rand_r(&random_state) % 256;
+        }
+        else
+        {
+            random[i]=rand() % 256;
+        }
+    }
+    (void)pthread_mutex_unlock(&random_mutex);
+
+    if(fault == SIM_FAULT_CORRUPT)
+    {
+        sim_corrupt_buffer(random, len_random);
     }
 
     return UTA_SUCCESS;
@@ -157,6 +343,30 @@ uta_rc sim_get_device_uuid(const uta_context_v1_t *sim_context, uint8_t *uuid)
     uint8_t tmp_uuid[16];
     int ret;
     int i;
+    uta_rc rc;
+    sim_fault_t fault;
+
+    rc = sim_inject_latency();
+    if(rc != UTA_SUCCESS)
+    {
+        return rc;
+    }
+
+    fault = sim_next_fault(&rc);
+    if(fault == SIM_FAULT_FAIL)
+    {
+        return rc;
+    }
+
+    if(getenv(ENV_REPLAY) != NULL)
+    {
+        rc = sim_replay("get_device_uuid", NULL, uuid, 16);
+        if((rc == UTA_SUCCESS) && (fault == SIM_FAULT_CORRUPT))
+        {
+            sim_corrupt_buffer(uuid, 16);
+        }
+        return rc;
+    }
     
     fileptr = fopen("/etc/machine-id", "rb");  // Open the file in binary mode
     if(fileptr == NULL)
@@ -186,15 +396,423 @@ uta_rc sim_get_device_uuid(const uta_context_v1_t *sim_context, uint8_t *uuid)
     /* Copy tmp_uuid to uuid */
     memcpy(uuid, tmp_uuid, 16);
 
+    if(fault == SIM_FAULT_CORRUPT)
+    {
+        sim_corrupt_buffer(uuid, 16);
+    }
+
     return UTA_SUCCESS;
 }
 
 /**
- * @brief Prototype of the self test function. (Not used in simulation)
+ * @brief Prototype of the self test function. (Not used in simulation, apart
+ *      from the fault injection and the replay)
  * @param[in,out] sim_context Pointer to the internal context struct.
  * @return UTA return code.
  */
 uta_rc sim_self_test(const uta_context_v1_t *sim_context)
 {
+    uta_rc rc;
+
+    rc = sim_inject_latency();
+    if(rc != UTA_SUCCESS)
+    {
+        return rc;
+    }
+
+    if(sim_next_fault(&rc) == SIM_FAULT_FAIL)
+    {
+        return rc;
+    }
+
+    if(getenv(ENV_REPLAY) != NULL)
+    {
+        return sim_replay("self_test", NULL, NULL, 0);
+    }
+
+    return UTA_SUCCESS;
+}
+
+/*******************************************************************************
+ * Private function bodies
+ ******************************************************************************/
+/**
+ * @brief Counts the trust anchor calls and decides, based on the environment
+ *      variables UTA_SIM_FAIL_CALL, UTA_SIM_FAIL_COUNT, UTA_SIM_FAIL_RC and
+ *      UTA_SIM_FAIL_MODE, whether the current call has to be manipulated. If
+ *      one of the variables contains a malformed value, every call fails with
+ *      UTA_TA_ERROR, so a typo cannot silently disable the fault injection.
+ * @param[out] rc Return code, which has to be returned by a failing call.
+ * @return Type of the fault to be injected into the current call.
+ */
+static sim_fault_t sim_next_fault(uta_rc *rc)
+{
+    unsigned long call;
+    unsigned long fail_call = 0;
+    unsigned long fail_count = 1;
+    unsigned long fail_rc = UTA_TA_ERROR;
+    const char *env;
+
+    (void)pthread_mutex_lock(&call_counter_mutex);
+    call = ++call_counter;
+    (void)pthread_mutex_unlock(&call_counter_mutex);
+
+    *rc = UTA_TA_ERROR;
+    if((sim_getenv_ulong(ENV_FAIL_CALL, ULONG_MAX, &fail_call) != UTA_SUCCESS)
+        || (sim_getenv_ulong(ENV_FAIL_COUNT, ULONG_MAX, &fail_count) !=
+        UTA_SUCCESS) || (sim_getenv_ulong(ENV_FAIL_RC, UINT32_MAX, &fail_rc)
+        != UTA_SUCCESS))
+    {
+        return SIM_FAULT_FAIL;
+    }
+
+    env = getenv(ENV_FAIL_MODE);
+    if((env != NULL) && (strcmp(env, "error") != 0) &&
+        (strcmp(env, "corrupt") != 0))
+    {
+        fprintf(stderr, "UTA_SIM: Invalid value '%s' of %s\n", env,
+            ENV_FAIL_MODE);
+        return SIM_FAULT_FAIL;
+    }
+
+    /* Fault injection is disabled if UTA_SIM_FAIL_CALL is not set or 0 */
+    if((fail_call == 0) || (call < fail_call) ||
+        (call - fail_call >= fail_count))
+    {
+        return SIM_FAULT_NONE;
+    }
+
+    if((env != NULL) && (strcmp(env, "corrupt") == 0))
+    {
+        return SIM_FAULT_CORRUPT;
+    }
+
+    /* A failing call must never report success without writing its output */
+    if(fail_rc != UTA_SUCCESS)
+    {
+        *rc = (uta_rc)fail_rc;
+    }
+
+    return SIM_FAULT_FAIL;
+}
+
+/**
+ * @brief Corrupts an output buffer by inverting all of its bits.
+ * @param[in,out] buffer Pointer to the buffer, which should be corrupted.
+ * @param[in] len_buffer Length of the buffer in bytes.
+ */
+static void sim_corrupt_buffer(uint8_t *buffer, size_t len_buffer)
+{
+    for(size_t i=0; i<len_buffer; i++)
+    {
+        buffer[i] ^= 0xff;
+    }
+}
+
+/**
+ * @brief Delays the current call by UTA_SIM_LATENCY_MS milliseconds plus a
+ *      uniformly distributed jitter between 0 and UTA_SIM_JITTER_MS
+ *      milliseconds to simulate slow hardware. Both values are limited to
+ *      MAX_LATENCY_MS.
+ * @return UTA_SUCCESS, or UTA_TA_ERROR if one of the variables contains a
+ *      malformed value.
+ */
+static uta_rc sim_inject_latency(void)
+{
+    unsigned long latency = 0;
+    unsigned long jitter = 0;
+    struct timespec delay;
+
+    if((sim_getenv_ulong(ENV_LATENCY, ULONG_MAX, &latency) != UTA_SUCCESS) ||
+        (sim_getenv_ulong(ENV_JITTER, ULONG_MAX, &jitter) != UTA_SUCCESS))
+    {
+        return UTA_TA_ERROR;
+    }
+
+    if(latency > MAX_LATENCY_MS)
+    {
+        latency = MAX_LATENCY_MS;
+    }
+
+    if(jitter > MAX_LATENCY_MS)
+    {
+        jitter = MAX_LATENCY_MS;
+    }
+
+    if(jitter > 0)
+    {
+        (void)pthread_mutex_lock(&jitter_mutex);
+        latency += (unsigned long)rand_r(&jitter_seed) % (jitter + 1);
+        (void)pthread_mutex_unlock(&jitter_mutex);
+    }
+
+    if(latency == 0)
+    {
+        return UTA_SUCCESS;
+    }
+
+    delay.tv_sec = (time_t)(latency / 1000);
+    delay.tv_nsec = (long)(latency % 1000) * 1000000L;
+    (void)nanosleep(&delay, NULL);
+
+    return UTA_SUCCESS;
+}
+
+/**
+ * @brief Reads an unsigned number from an environment variable. Decimal and
+ *      hexadecimal (0x..) values are accepted.
+ * @param[in] name Name of the environment variable.
+ * @param[in] max Highest accepted value.
+ * @param[in,out] value Parsed value. It is left untouched if the variable is
+ *      not set, so the caller can preset the default value.
+ * @return UTA_SUCCESS if the variable is not set or contains a valid value,
+ *      UTA_TA_ERROR if the value is malformed, negative or out of range.
+ */
+static uta_rc sim_getenv_ulong(const char *name, unsigned long max,
+    unsigned long *value)
+{
+    const char *env;
+    char *endptr;
+    unsigned long tmp;
+
+    env = getenv(name);
+    if(env == NULL)
+    {
+        return UTA_SUCCESS;
+    }
+
+    /* strtoul silently negates negative numbers, so they are rejected here */
+    errno = 0;
+    tmp = strtoul(env, &endptr, 0);
+    if((strchr(env, '-') != NULL) || (endptr == env) || (*endptr != '\0') ||
+        (errno == ERANGE) || (tmp > max))
+    {
+        fprintf(stderr, "UTA_SIM: Invalid value '%s' of %s\n", env, name);
+        return UTA_TA_ERROR;
+    }
+
+    *value = tmp;
+    return UTA_SUCCESS;
+}
+
+/**
+ * @brief Replays the next call from the cassette given in UTA_SIM_REPLAY. The
+ *      cassette is written by the recording of the library (configure flag
+ *      --enable-recording) and contains one call per line:
+ *      <operation>[ <parameters>] <return code>[ <output as hex>]
+ *      Empty lines and lines starting with '#' are skipped.
+ * @param[in] operation Name of the called function.
+ * @param[in] params Parameters of the call as written by the recording, or
+ *      NULL if the function has no parameters.
+ * @param[out] output Output buffer of the call, or NULL if the function has no
+ *      output buffer.
+ * @param[in] len_output Length of the output buffer in bytes.
+ * @return Recorded return code, or UTA_TA_ERROR if the call does not match
+ *      the next entry of the cassette. A non-matching entry is left unread,
+ *      so the following call is compared with it again.
+ */
+static uta_rc sim_replay(const char *operation, const char *params,
+    uint8_t *output, size_t len_output)
+{
+    const char *path;
+    char *line = NULL;
+    size_t len_line = 0;
+    ssize_t ret;
+    long pos;
+    uta_rc rc;
+
+    (void)pthread_mutex_lock(&replay_mutex);
+    if(replay_file == NULL)
+    {
+        path = getenv(ENV_REPLAY);
+        replay_file = fopen(path, "r");
+        if(replay_file == NULL)
+        {
+            (void)pthread_mutex_unlock(&replay_mutex);
+            fprintf(stderr, "UTA_SIM: Cannot open the cassette '%s'\n", path);
+            return UTA_TA_ERROR;
+        }
+    }
+
+    /* Skip empty lines and comments */
+    do
+    {
+        pos = ftell(replay_file);
+        ret = getline(&line, &len_line, replay_file);
+    } while((ret > 0) && ((line[0] == '#') || (line[0] == '\n')));
+
+    if(ret <= 0)
+    {
+        (void)pthread_mutex_unlock(&replay_mutex);
+        free(line);
+        fprintf(stderr, "UTA_SIM: End of the cassette reached by %s\n",
+            operation);
+        return UTA_TA_ERROR;
+    }
+    line[strcspn(line, "\r\n")] = '\0';
+
+    if(sim_replay_parse(line, operation, params, output, len_output, &rc) !=
+        UTA_SUCCESS)
+    {
+        /* Leave the entry unread, so the replay does not get out of step */
+        (void)fseek(replay_file, pos, SEEK_SET);
+        (void)pthread_mutex_unlock(&replay_mutex);
+        fprintf(stderr, "UTA_SIM: Call '%s%s%s' does not match the cassette "
+            "entry '%s'\n", operation, (params != NULL) ? " " : "",
+            (params != NULL) ? params : "", line);
+        free(line);
+        return UTA_TA_ERROR;
+    }
+    (void)pthread_mutex_unlock(&replay_mutex);
+
+    free(line);
+    return rc;
+}
+
+/**
+ * @brief Compares a cassette entry with the current call and extracts the
+ *      recorded return code and output.
+ * @param[in] line Cassette entry without line break.
+ * @param[in] operation Name of the called function.
+ * @param[in] params Parameters of the call, or NULL.
+ * @param[out] output Output buffer of the call, or NULL.
+ * @param[in] len_output Length of the output buffer in bytes.
+ * @param[out] rc Recorded return code.
+ * @return UTA_SUCCESS if the entry matches the call, UTA_TA_ERROR otherwise.
+ */
+static uta_rc sim_replay_parse(const char *line, const char *operation,
+    const char *params, uint8_t *output, size_t len_output, uta_rc *rc)
+{
+    const char *ptr = line;
+    char *endptr;
+    unsigned long recorded_rc;
+    size_t len;
+
+    /* Compare the operation and the parameters */
+    len = strlen(operation);
+    if((strncmp(ptr, operation, len) != 0) || (ptr[len] != ' '))
+    {
+        return UTA_TA_ERROR;
+    }
+    ptr += len + 1;
+
+    if(params != NULL)
+    {
+        len = strlen(params);
+        if((strncmp(ptr, params, len) != 0) || (ptr[len] != ' '))
+        {
+            return UTA_TA_ERROR;
+        }
+        ptr += len + 1;
+    }
+
+    /* Read the recorded return code */
+    errno = 0;
+    recorded_rc = strtoul(ptr, &endptr, 0);
+    if((endptr == ptr) || (*ptr == '-') || (errno == ERANGE) ||
+        (recorded_rc > UINT32_MAX))
+    {
+        return UTA_TA_ERROR;
+    }
+    ptr = endptr;
+
+    if(output == NULL)
+    {
+        if(*ptr != '\0')
+        {
+            return UTA_TA_ERROR;
+        }
+    }
+    else
+    {
+        if(*ptr != ' ')
+        {
+            return UTA_TA_ERROR;
+        }
+        ptr++;
+
+        /* The output is only recorded for successful calls */
+        if((recorded_rc == UTA_SUCCESS) && (len_output > 0))
+        {
+            if((strlen(ptr) != 2*len_output) ||
+                (sim_hex2bytes(ptr, output, len_output) != UTA_SUCCESS))
+            {
+                return UTA_TA_ERROR;
+            }
+        }
+        else if(strcmp(ptr, "-") != 0)
+        {
+            return UTA_TA_ERROR;
+        }
+    }
+
+    *rc = (uta_rc)recorded_rc;
+    return UTA_SUCCESS;
+}
+
+/**
+ * @brief Formats the parameters of derive_key like the recording and replays
+ *      the call.
+ * @param[out] key Pointer to the buffer where the derived key is written to.
+ * @param[in] len_key Number of bytes, which should be written to key.
+ * @param[in] dv Pointer to the derivation value.
+ * @param[in] len_dv Length of the derivation value in bytes.
+ * @param[in] key_slot Key slot used for the derivation.
+ * @return Recorded return code, or UTA_TA_ERROR if the call does not match
+ *      the next entry of the cassette.
+ */
+static uta_rc sim_replay_derive_key(uint8_t *key, size_t len_key,
+    const uint8_t *dv, size_t len_dv, uint8_t key_slot)
+{
+    size_t len_params = 2*len_dv + 48;
+    char *params;
+    int pos;
+    uta_rc rc;
+
+    params = malloc(len_params);
+    if(params == NULL)
+    {
+        return UTA_TA_ERROR;
+    }
+
+    pos = snprintf(params, len_params, "%u %zu ", (unsigned int)key_slot,
+        len_key);
+    if(len_dv == 0)
+    {
+        (void)strcpy(&params[pos], "-");
+    }
+    for(size_t i=0; i<len_dv; i++)
+    {
+        pos += snprintf(&params[pos], len_params - pos, "%02x",
+            (unsigned int)dv[i]);
+    }
+
+    rc = sim_replay("derive_key", params, key, len_key);
+    free(params);
+
+    return rc;
+}
+
+/**
+ * @brief Converts a hex string into bytes.
+ * @param[in] hex Hex string with exactly 2*len_buffer characters.
+ * @param[out] buffer Buffer for the converted bytes.
+ * @param[in] len_buffer Length of the buffer in bytes.
+ * @return UTA_SUCCESS, or UTA_TA_ERROR if the string contains non-hex
+ *      characters.
+ */
+static uta_rc sim_hex2bytes(const char *hex, uint8_t *buffer,
+    size_t len_buffer)
+{
+    for(size_t i=0; i<len_buffer; i++)
+    {
+        if(!isxdigit((unsigned char)hex[i*2]) ||
+            !isxdigit((unsigned char)hex[i*2+1]) ||
+            (sscanf(&hex[i*2], "%2hhx", &buffer[i]) != 1))
+        {
+            return UTA_TA_ERROR;
+        }
+    }
+
     return UTA_SUCCESS;
 }
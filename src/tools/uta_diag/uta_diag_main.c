@@ -0,0 +1,628 @@
+/** @file uta_diag_main.c
+*
+* @brief Collect diagnostic information about the TA and its environment in a
+* report for support requests or check the environment before installation
+*
+* @copyright Copyright (c) Siemens Mobility GmbH, 2020
+*
+* @license This work is licensed under the terms of the Apache Software License
+* 2.0. See the COPYING file in the top-level directory.
+*
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+/*******************************************************************************
+ * Includes
+ ******************************************************************************/
+#include <unistd.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <errno.h>
+#include <ctype.h>
+#include <time.h>
+#include <limits.h>
+#include <grp.h>
+#include <pwd.h>
+#include <sys/stat.h>
+#include <sys/types.h>
+#include <sys/utsname.h>
+#include <config.h>
+
+#include <uta.h>
+
+/*******************************************************************************
+ * Defines
+ ******************************************************************************/
+#define UUID_BYTES       16
+#define RANDOM_BYTES     32
+
+/* Number of hexadecimal characters of /etc/machine-id */
+#define MACHINE_ID_CHARS 32
+
+/* Upper limit for the number of supplementary groups of the process */
+#define GROUPS_MAX       256
+
+/*******************************************************************************
+ * Static data declaration
+ ******************************************************************************/
+/* Number of failed checks, determines the exit status */
+static unsigned int failed_checks = 0;
+
+/*******************************************************************************
+ * Private function prototypes
+ ******************************************************************************/
+static void report_check(FILE *out, int ok, const char *name,
+                         const char *detail);
+static void report_rc(FILE *out, const char *name, uta_rc rc);
+static void mode2str(mode_t mode, char *str);
+static int in_group(gid_t gid);
+static void report_system(FILE *out);
+static void report_groups(FILE *out);
+static void check_path(FILE *out, const char *path, int access_mode);
+#ifdef HW_BACKEND_UTA_SIM
+static void check_machine_id(FILE *out);
+#endif
+#ifdef RECORDING
+static void check_cassette(FILE *out, const char *path);
+#endif
+static void report_environment(FILE *out);
+static void report_library(FILE *out, uta_api_v1_t *uta, uta_rc init_rc);
+static void report_preflight_library(FILE *out);
+static void report_trust_anchor(FILE *out, uta_api_v1_t *uta, int show_uuid);
+
+/*******************************************************************************
+ * Private function bodies
+ ******************************************************************************/
+
+/**
+ * @brief Writes the result of a check to the report and counts the failed
+ *      checks.
+ * @param[in] out Report stream.
+ * @param[in] ok 1 if the check passed, 0 otherwise.
+ * @param[in] name Name of the check.
+ * @param[in] detail Additional information, may be NULL.
+ */
+static void report_check(FILE *out, int ok, const char *name,
+                         const char *detail)
+{
+    if (0 == ok)
+    {
+        failed_checks++;
+    }
+    fprintf(out, "[%s] %s%s%s\n", (1 == ok) ? " OK " : "FAIL", name,
+            (NULL != detail) ? ": " : "", (NULL != detail) ? detail : "");
+}
+
+/**
+ * @brief Writes the return code of a trust anchor call to the report.
+ * @param[in] out Report stream.
+ * @param[in] name Name of the call.
+ * @param[in] rc Return code of the call.
+ */
+static void report_rc(FILE *out, const char *name, uta_rc rc)
+{
+    char detail[32];
+
+    (void)snprintf(detail, sizeof(detail), "rc 0x%02x", rc);
+    report_check(out, UTA_SUCCESS == rc, name, detail);
+}
+
+/**
+ * @brief Converts the mode of a file to the notation of 'ls -l'.
+ * @param[in] mode Mode of the file as returned by stat.
+ * @param[out] str Buffer for the string, at least 11 characters long.
+ */
+static void mode2str(mode_t mode, char *str)
+{
+    const char rwx[] = "rwxrwxrwx";
+
+    str[0] = S_ISDIR(mode) ? 'd' : S_ISCHR(mode) ? 'c' : S_ISREG(mode) ? '-' : '?';
+    for (int i = 0; i < 9; i++)
+    {
+        str[i+1] = (mode & (1 << (8 - i))) ? rwx[i] : '-';
+    }
+    str[10] = '\0';
+}
+
+/**
+ * @brief Checks whether the process is member of a group.
+ * @param[in] gid Group id to check.
+ * @return returns 1 if the process is member of the group,
+ *         returns 0 otherwise.
+ */
+static int in_group(gid_t gid)
+{
+    gid_t groups[GROUPS_MAX];
+    int ngroups;
+
+    if (getegid() == gid)
+    {
+        return 1;
+    }
+
+    ngroups = getgroups(GROUPS_MAX, groups);
+    for (int i = 0; i < ngroups; i++)
+    {
+        if (groups[i] == gid)
+        {
+            return 1;
+        }
+    }
+    return 0;
+}
+
+/**
+ * @brief Writes the date, the kernel and the ids of the process to the report.
+ * @param[in] out Report stream.
+ */
+static void report_system(FILE *out)
+{
+    struct utsname uts;
+    struct tm tm_now;
+    time_t now;
+    char date[32];
+
+    now = time(NULL);
+    if ((NULL != gmtime_r(&now, &tm_now)) &&
+        (0 != strftime(date, sizeof(date), "%Y-%m-%dT%H:%M:%SZ", &tm_now)))
+    {
+        fprintf(out, "date: %s\n", date);
+    }
+
+    if (0 == uname(&uts))
+    {
+        fprintf(out, "kernel: %s %s %s\n", uts.sysname, uts.release,
+                uts.machine);
+    }
+
+    fprintf(out, "uid: %u, euid: %u, gid: %u, egid: %u\n",
+            (unsigned int)getuid(), (unsigned int)geteuid(),
+            (unsigned int)getgid(), (unsigned int)getegid());
+}
+
+/**
+ * @brief Writes the supplementary groups of the process to the report.
+ * @param[in] out Report stream.
+ */
+static void report_groups(FILE *out)
+{
+    gid_t groups[GROUPS_MAX];
+    struct group *grp;
+    int ngroups;
+
+    fprintf(out, "groups:");
+    ngroups = getgroups(GROUPS_MAX, groups);
+    for (int i = 0; i < ngroups; i++)
+    {
+        grp = getgrgid(groups[i]);
+        if (NULL != grp)
+        {
+            fprintf(out, " %s", grp->gr_name);
+        }
+        else
+        {
+            fprintf(out, " %u", (unsigned int)groups[i]);
+        }
+    }
+    fprintf(out, "\n");
+}
+
+/**
+ * @brief Checks the existence and the access permissions of a file, device or
+ *      directory used by the library and writes the result to the report. If
+ *      the access is denied, a missing group membership is reported as well.
+ * @param[in] out Report stream.
+ * @param[in] path Path to check.
+ * @param[in] access_mode Required access as for access(2), e.g. R_OK | W_OK.
+ */
+static void check_path(FILE *out, const char *path, int access_mode)
+{
+    struct stat st;
+    struct passwd *pwd;
+    struct group *grp;
+    char mode[11];
+    char owner[32];
+    char group[32];
+    char detail[256];
+    int err;
+
+    if (0 != stat(path, &st))
+    {
+        report_check(out, 0, path, strerror(errno));
+        return;
+    }
+
+    mode2str(st.st_mode, mode);
+    pwd = getpwuid(st.st_uid);
+    grp = getgrgid(st.st_gid);
+    if (NULL != pwd)
+    {
+        (void)snprintf(owner, sizeof(owner), "%s", pwd->pw_name);
+    }
+    else
+    {
+        (void)snprintf(owner, sizeof(owner), "%u", (unsigned int)st.st_uid);
+    }
+    if (NULL != grp)
+    {
+        (void)snprintf(group, sizeof(group), "%s", grp->gr_name);
+    }
+    else
+    {
+        (void)snprintf(group, sizeof(group), "%u", (unsigned int)st.st_gid);
+    }
+
+    if (0 == access(path, access_mode))
+    {
+        (void)snprintf(detail, sizeof(detail), "%s %s:%s", mode, owner, group);
+        report_check(out, 1, path, detail);
+    }
+    else
+    {
+        err = errno;
+        if (1 == in_group(st.st_gid))
+        {
+            (void)snprintf(detail, sizeof(detail), "%s %s:%s, %s", mode, owner,
+                           group, strerror(err));
+        }
+        else
+        {
+            (void)snprintf(detail, sizeof(detail),
+                           "%s %s:%s, %s (not member of group '%s')", mode,
+                           owner, group, strerror(err), group);
+        }
+        report_check(out, 0, path, detail);
+    }
+}
+
+#ifdef HW_BACKEND_UTA_SIM
+/**
+ * @brief Checks whether /etc/machine-id contains a valid machine id, which is
+ *      used by get_device_uuid of the simulator.
+ * @param[in] out Report stream.
+ */
+static void check_machine_id(FILE *out)
+{
+    FILE *fileptr;
+    char machine_id[MACHINE_ID_CHARS];
+    int ok;
+
+    fileptr = fopen("/etc/machine-id", "rb");
+    if (NULL == fileptr)
+    {
+        return;
+    }
+    ok = (MACHINE_ID_CHARS == fread(machine_id, 1, MACHINE_ID_CHARS, fileptr));
+    (void)fclose(fileptr);
+
+    for (int i = 0; (1 == ok) && (i < MACHINE_ID_CHARS); i++)
+    {
+        ok = (0 != isxdigit((unsigned char)machine_id[i]));
+    }
+    report_check(out, ok, "machine id", (1 == ok) ? NULL :
+                 "expected 32 hexadecimal characters in /etc/machine-id");
+}
+#endif
+
+#ifdef RECORDING
+/**
+ * @brief Checks whether the cassette given in UTA_RECORD can be written. The
+ *      recording appends to an existing cassette or creates it, so the
+ *      directory has to be writable if it does not exist yet.
+ * @param[in] out Report stream.
+ * @param[in] path Path of the cassette.
+ */
+static void check_cassette(FILE *out, const char *path)
+{
+    struct stat st;
+    char dir[PATH_MAX];
+    char *slash;
+
+    if (0 == stat(path, &st))
+    {
+        if (S_ISDIR(st.st_mode))
+        {
+            report_check(out, 0, path, "is a directory");
+            return;
+        }
+        check_path(out, path, W_OK);
+        return;
+    }
+
+    (void)snprintf(dir, sizeof(dir), "%s", path);
+    slash = strrchr(dir, '/');
+    if (NULL == slash)
+    {
+        (void)snprintf(dir, sizeof(dir), ".");
+    }
+    else if (slash == dir)
+    {
+        dir[1] = '\0';
+    }
+    else
+    {
+        *slash = '\0';
+    }
+    check_path(out, dir, W_OK | X_OK);
+}
+#endif
+
+/**
+ * @brief Checks the files and devices used by the configured backend. The
+ *      paths correspond to the chapter 'Sandboxing' of the README.
+ * @param[in] out Report stream.
+ */
+static void report_environment(FILE *out)
+{
+    fprintf(out, "\n[environment]\n");
+    report_groups(out);
+
+#ifdef RECORDING
+    if (NULL != getenv("UTA_RECORD"))
+    {
+        fprintf(out, "UTA_RECORD=%s (INSECURE: the cassette contains the derived keys)\n",
+                getenv("UTA_RECORD"));
+        check_cassette(out, getenv("UTA_RECORD"));
+    }
+#endif
+
+#ifdef HW_BACKEND_UTA_SIM
+    const char *sim_env[] = {"UTA_SIM_FAIL_CALL", "UTA_SIM_FAIL_COUNT",
+                             "UTA_SIM_FAIL_RC", "UTA_SIM_FAIL_MODE",
+                             "UTA_SIM_LATENCY_MS", "UTA_SIM_JITTER_MS",
+                             "UTA_SIM_SEED", "UTA_SIM_REPLAY"};
+
+    for (size_t i = 0; i < sizeof(sim_env) / sizeof(sim_env[0]); i++)
+    {
+        if (NULL != getenv(sim_env[i]))
+        {
+            fprintf(out, "%s=%s\n", sim_env[i], getenv(sim_env[i]));
+        }
+    }
+    check_path(out, "/etc/machine-id", R_OK);
+    check_machine_id(out);
+    if (NULL != getenv("UTA_SIM_REPLAY"))
+    {
+        check_path(out, getenv("UTA_SIM_REPLAY"), R_OK);
+    }
+#endif
+
+#ifdef HW_BACKEND_TPM_TCG
+    check_path(out, "/dev/tpm0", R_OK | W_OK);
+#endif
+
+#ifdef HW_BACKEND_TPM_IBM
+    const char *interface_type = getenv("TPM_INTERFACE_TYPE");
+    const char *device = getenv("TPM_DEVICE");
+    const char *data_dir = getenv("TPM_DATA_DIR");
+
+    /* The defaults are compiled into the IBM TSS, the paths of the setup
+     * described in the README are assumed. They can be overridden with the
+     * environment variables of the TSS. */
+    if ((NULL != interface_type) && (0 != strcmp(interface_type, "dev")))
+    {
+        fprintf(out, "TPM_INTERFACE_TYPE=%s (no device used)\n", interface_type);
+    }
+    else
+    {
+        if (NULL == device)
+        {
+            device = (0 == access("/dev/tpmrm0", F_OK)) ? "/dev/tpmrm0" : "/dev/tpm0";
+        }
+        check_path(out, device, R_OK | W_OK);
+    }
+    check_path(out, (NULL != data_dir) ? data_dir : "/var/lib/tpm_ibm",
+               R_OK | W_OK | X_OK);
+#endif
+}
+
+/**
+ * @brief Writes the backend, the version and the build options of the library
+ *      to the report.
+ * @param[in] out Report stream.
+ * @param[in] uta Function table filled by uta_init_v1.
+ * @param[in] init_rc Return code of uta_init_v1.
+ */
+static void report_library(FILE *out, uta_api_v1_t *uta, uta_rc init_rc)
+{
+    const char *uta_type[] = {"UTA_SIM", "TPM_IBM", "TPM_TCG"};
+    uta_version_t version;
+
+    fprintf(out, "\n[library]\n");
+    report_rc(out, "uta_init_v1", init_rc);
+    if (UTA_SUCCESS != init_rc)
+    {
+        return;
+    }
+
+    if (UTA_SUCCESS == uta->get_version(NULL, &version))
+    {
+        fprintf(out, "backend: %s\n", (version.uta_type <= TPM_TCG) ?
+                uta_type[version.uta_type] : "unknown");
+        fprintf(out, "version: %u.%u.%u\n", version.major, version.minor,
+                version.patch);
+    }
+    fprintf(out, "options:");
+#ifdef RECORDING
+    fprintf(out, " recording");
+#endif
+#ifdef OPERATIONAL_GATING
+    fprintf(out, " operational-gating");
+#endif
+#ifdef MULTIPROCESSING
+    fprintf(out, " multiprocessing");
+#endif
+    fprintf(out, "\n");
+}
+
+/**
+ * @brief Writes the library information to the report of the preflight
+ *      check. uta_init_v1 is skipped if it would call the trust anchor or
+ *      create a file.
+ * @param[in] out Report stream.
+ */
+static void report_preflight_library(FILE *out)
+{
+#ifdef OPERATIONAL_GATING
+    fprintf(out, "\n[library]\n");
+    fprintf(out, "uta_init_v1: skipped, it runs the power-on self-test\n");
+#else
+    uta_api_v1_t uta;
+
+#ifdef RECORDING
+    if (NULL != getenv("UTA_RECORD"))
+    {
+        fprintf(out, "\n[library]\n");
+        fprintf(out, "uta_init_v1: skipped, UTA_RECORD is set\n");
+        return;
+    }
+#endif
+
+    report_library(out, &uta, uta_init_v1(&uta));
+#endif
+}
+
+/**
+ * @brief Calls the trust anchor and writes the return codes to the report.
+ *      No key is derived and neither the random numbers nor (by default) the
+ *      device UUID are written to the report.
+ * @param[in] out Report stream.
+ * @param[in] uta Function table filled by uta_init_v1.
+ * @param[in] show_uuid 1 to write the device UUID to the report.
+ */
+static void report_trust_anchor(FILE *out, uta_api_v1_t *uta, int show_uuid)
+{
+    uta_context_v1_t *uta_context;
+    uint8_t uuid[UUID_BYTES];
+    uint8_t random[RANDOM_BYTES];
+    uta_rc rc;
+
+    fprintf(out, "\n[trust anchor]\n");
+
+    uta_context = malloc(uta->context_v1_size());
+    if (NULL == uta_context)
+    {
+        report_check(out, 0, "open", "out of memory");
+        return;
+    }
+
+    rc = uta->open(uta_context);
+    report_rc(out, "open", rc);
+    if (UTA_SUCCESS != rc)
+    {
+        free(uta_context);
+        return;
+    }
+
+    report_rc(out, "self_test", uta->self_test(uta_context));
+    report_rc(out, "get_random", uta->get_random(uta_context, random,
+                                                 RANDOM_BYTES));
+
+    rc = uta->get_device_uuid(uta_context, uuid);
+    report_rc(out, "get_device_uuid", rc);
+    if ((UTA_SUCCESS == rc) && (1 == show_uuid))
+    {
+        fprintf(out, "uuid: ");
+        for (int i = 0; i < UUID_BYTES; i++)
+        {
+            fprintf(out, "%02x", uuid[i]);
+        }
+        fprintf(out, "\n");
+    }
+    else if (UTA_SUCCESS == rc)
+    {
+        fprintf(out, "uuid: <redacted>\n");
+    }
+
+    report_rc(out, "close", uta->close(uta_context));
+    free(uta_context);
+}
+
+/**
+ * @brief Command line interface to collect diagnostic information about the
+ *      trust anchor. In the preflight mode (-p), only the environment is
+ *      checked and the trust anchor is not called.
+ * @return exit status 0 if all checks passed,
+ *         exit status 2 if at least one check failed,
+ *         exit status 1 in case of an error
+ */
+int main(int argc, char *argv[])
+{
+   uta_api_v1_t uta;
+   uta_rc init_rc;
+   FILE *out = stdout;
+   char *oval = NULL;
+   int show_uuid = 0;
+   int preflight = 0;
+   int c;
+
+   while ((c = getopt (argc, argv, "o:uph")) != -1)
+   {
+       switch(c)
+       {
+       case 'o':
+          oval = optarg;
+          break;
+       case 'u':
+          show_uuid = 1;
+          break;
+       case 'p':
+          preflight = 1;
+          break;
+       case '?':
+       case 'h':
+          fprintf(stderr, "### Collect diagnostic information about the UTA trust anchor ### \n\n");
+          fprintf(stderr, "Usage: uta_diag [-o <report_file>] [-u] [-p] [-h]\n\n");
+          fprintf(stderr, "-o <report_file>: write the report to <report_file>;\n");
+          fprintf(stderr, "   (default: stdout)\n");
+          fprintf(stderr, "-u: include the device UUID in the report;\n");
+          fprintf(stderr, "   (default: the UUID is redacted)\n");
+          fprintf(stderr, "-p: preflight check of the environment only,\n");
+          fprintf(stderr, "   the trust anchor is not called\n");
+          fprintf(stderr, "-h This help message\n");
+          return 1;
+       }
+   }
+
+   if (NULL != oval)
+   {
+      out = fopen(oval, "w");
+      if (NULL == out)
+      {
+         fprintf(stderr, "ERROR: Cannot open '%s': %s\n", oval, strerror(errno));
+         return 1;
+      }
+   }
+
+   if (1 == preflight)
+   {
+      fprintf(out, "### UTA preflight report ###\n");
+      report_system(out);
+      report_preflight_library(out);
+      report_environment(out);
+   }
+   else
+   {
+      fprintf(out, "### UTA diagnostics report ###\n");
+      report_system(out);
+      init_rc = uta_init_v1(&uta);
+      report_library(out, &uta, init_rc);
+      report_environment(out);
+      if (UTA_SUCCESS == init_rc)
+      {
+         report_trust_anchor(out, &uta, show_uuid);
+      }
+   }
+
+   fprintf(out, "\n[summary]\n");
+   fprintf(out, "failed checks: %u\n", failed_checks);
+
+   if ((stdout != out) && (0 != fclose(out)))
+   {
+      fprintf(stderr, "ERROR: Cannot write '%s'\n", oval);
+      return 1;
+   }
+
+   return (0 == failed_checks) ? 0 : 2;
+}
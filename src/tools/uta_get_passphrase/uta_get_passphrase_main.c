@@ -50,6 +50,7 @@ static int bytes2base64(const char *input_data, size_t input_length,
 static int get_passphrase_from_ta(char **passphrase,
            const char *derivation_string, uint8_t key_slot,
            string_encoding_t string_encoding);
+static void zeroize(void *buffer, size_t len_buffer);
 
 /*******************************************************************************
  * Private function bodies
@@ -135,6 +136,22 @@ static int bytes2base64(const char *input_data, size_t input_length,
     return 0;
 }
 
+/**
+ * @brief Overwrites a buffer containing key material with zeros. The volatile
+ *      access prevents the compiler from removing the writes.
+ * @param[in,out] buffer Pointer to the buffer.
+ * @param[in] len_buffer Length of the buffer in bytes.
+ */
+static void zeroize(void *buffer, size_t len_buffer)
+{
+    volatile char *p = buffer;
+
+    while (len_buffer--)
+    {
+        *p++ = 0;
+    }
+}
+
 /**
  * @brief Get passphrase from trust anchor.
  * @param[out] passphrase Buffer containing the derived passphrase.
@@ -202,6 +219,7 @@ static int get_passphrase_from_ta(char **passphrase,
     rc = uta.close(uta_context);
     if (UTA_SUCCESS != rc)
     {
+        zeroize(key, TA_KEY_BYTES);
         free(uta_context);
         return 1;
     }
@@ -210,19 +228,13 @@ static int get_passphrase_from_ta(char **passphrase,
     uta_context=NULL;
 
     // convert binary key data into a printable string (passphrase)
+    int ret = 1;
     if (BASE64_ENCODING == string_encoding)
     {
         *passphrase = malloc((4 * ((TA_KEY_BYTES + 2) / 3)) + 1 );
         if (NULL != *passphrase)
         {
-            if(0 != bytes2base64(key, TA_KEY_BYTES, *passphrase, (4 * ((TA_KEY_BYTES + 2) / 3)) + 1 ))
-            {
-                return 1;
-            }
-        }
-        else
-        {
-           return 1;
+            ret = bytes2base64(key, TA_KEY_BYTES, *passphrase, (4 * ((TA_KEY_BYTES + 2) / 3)) + 1 );
         }
     }
     else if (HEX_ENCODING == string_encoding)
@@ -230,21 +242,13 @@ static int get_passphrase_from_ta(char **passphrase,
         *passphrase = malloc(TA_KEY_BYTES * 2 + 1);
         if (NULL != *passphrase)
         {
-            if(0 != bytes2hexstr(key, TA_KEY_BYTES, *passphrase, TA_KEY_BYTES * 2 + 1))
-            {
-                return 1;
-            }
-        }
-        else
-        {
-            return 1;
+            ret = bytes2hexstr(key, TA_KEY_BYTES, *passphrase, TA_KEY_BYTES * 2 + 1);
         }
     }
-    else
-    {
-       return 1;
-    }
-    return 0;
+
+    // the key is only needed in the encoded form
+    zeroize(key, TA_KEY_BYTES);
+    return ret;
 }
 
 /**
@@ -365,6 +369,7 @@ int main(int argc, char *argv[])
    }
    printf("%s\n", passphrase);
 
+   zeroize(passphrase, strlen(passphrase));
    free(passphrase);
    if(0 == dflag)
    {
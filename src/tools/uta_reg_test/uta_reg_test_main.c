@@ -53,6 +53,32 @@ typedef int(*test_case_t)(uta_context_v1_t *uta_context);
 #define CHI2_UPPER       25.0295
 #define CHI2_NUM_REPEATS 5
 #define CHI2_N_SAMPLES   128      // Samplesize is 4 bit
+
+/* Parameters for the latency injection test of the simulator */
+#define SIM_LATENCY_MS   100
+
+/*
+ * Number of calls of the power-on self-test in uta_init_v1 (self_test and
+ * derive_key), which are counted by the fault injection of the simulator
+ */
+#ifdef OPERATIONAL_GATING
+#define SIM_POST_CALLS   2
+#else
+#define SIM_POST_CALLS   0
+#endif
+
+/* Number of random bytes compared by the seeded RNG test of the simulator */
+#define SIM_SEED_LEN     32
+
+/* Cassette replayed by the replay test of the simulator */
+#define SIM_CASSETTE     "# Cassette of the replay test\n" \
+                         "open 0x00\n" \
+                         "derive_key 0 4 7265706c61792121 0x00 deadbeef\n" \
+                         "get_random 4 0x00 01020304\n" \
+                         "\n" \
+                         "get_device_uuid 0x10 -\n" \
+                         "self_test 0x00\n" \
+                         "close 0x00\n"
    
 /*******************************************************************************
  * Static data declaration
@@ -77,6 +103,25 @@ static int read_keys(char **key_files, int num);
 static void print_usage(char *name);
 static void *thread_test_1(void *uta_context);
 static void *thread_test_2();
+#ifdef HW_BACKEND_UTA_SIM
+static int run_sim_test(test_case_t test_case, uta_context_v1_t *uta_context);
+static int test_sim_fault_window(uta_context_v1_t *uta_context);
+static int test_sim_fault_corrupt(uta_context_v1_t *uta_context);
+static int test_sim_latency(uta_context_v1_t *uta_context);
+static int test_sim_seed(uta_context_v1_t *uta_context);
+static int read_seeded_random(uta_context_v1_t *uta_context, const char *seed,
+        uint8_t *random, size_t len_random);
+static int test_sim_replay(uta_context_v1_t *uta_context);
+static int write_temp_file(char *path, const char *content);
+#ifdef RECORDING
+static int test_sim_record(uta_context_v1_t *uta_context);
+#endif
+static long elapsed_ms(const struct timespec *start);
+static void set_sim_fail_call(unsigned long call);
+#ifdef OPERATIONAL_GATING
+static int test_sim_gating(void);
+#endif
+#endif
 
 /* Define the test cases */
 test_case_t test_cases[] = {\
@@ -87,6 +132,25 @@ test_case_t test_cases[] = {\
                                  test_derive_key, \
                                  0 };
 
+#ifdef HW_BACKEND_UTA_SIM
+/*
+ * Simulator specific test cases. Each test case runs in a separate child
+ * process with a clean UTA_SIM_* environment. They have to be executed before
+ * any other test case, because the fault injection counts the calls since the
+ * library has been loaded and the child processes inherit this counter.
+ */
+test_case_t sim_test_cases[] = {
+                                 test_sim_fault_window,
+                                 test_sim_fault_corrupt,
+                                 test_sim_latency,
+                                 test_sim_seed,
+                                 test_sim_replay,
+#ifdef RECORDING
+                                 test_sim_record,
+#endif
+                                 0 };
+#endif
+
 /*******************************************************************************
  * Public function bodies
  ******************************************************************************/
@@ -147,6 +211,16 @@ int main(int argc, char **argv)
 
     srand((unsigned) time(&t));
 
+#if defined(HW_BACKEND_UTA_SIM) && defined(OPERATIONAL_GATING)
+    /* The power-on self-test runs in the first uta_init_v1 of a process */
+    printf("Run the operational gating test (separate process)\n");
+    if(test_sim_gating() != 0)
+    {
+        success = 0;
+    }
+    printf("\n");
+#endif
+
     rc = uta_init_v1(&uta);
     if (rc != UTA_SUCCESS)
     {
@@ -162,6 +236,20 @@ int main(int argc, char **argv)
         return 1;
     }
     
+#ifdef HW_BACKEND_UTA_SIM
+    printf("Run the simulator specific tests (separate processes)\n");
+
+    for(i = 0; sim_test_cases[i]; i++)
+    {
+        ret = run_sim_test(sim_test_cases[i], uta_context);
+        if(ret != 0)
+        {
+            success = 0;
+        }
+    }
+    printf("\n");
+#endif
+
     printf("Run all the tests once (single thread/process)\n");
 
     for(i = 0; test_cases[i]; i++)
@@ -643,3 +731,608 @@ static void *thread_test_2()
     free(uta_context);
     pthread_exit((void *)0);
 }
+
+#ifdef HW_BACKEND_UTA_SIM
+/**
+ * @brief Runs a simulator specific test case in a child process. The child
+ *      clears all UTA_SIM_* environment variables, opens the context, runs the
+ *      test case and closes the context again.
+ * @param[in] test_case Test case to be executed.
+ * @param[in,out] uta_context Pointer to the uta_context struct.
+ * @return In case of success the function returns 0, 1 otherwise.
+ */
+static int run_sim_test(test_case_t test_case, uta_context_v1_t *uta_context)
+{
+    const char *sim_env[] = {"UTA_SIM_FAIL_CALL", "UTA_SIM_FAIL_COUNT",
+        "UTA_SIM_FAIL_RC", "UTA_SIM_FAIL_MODE", "UTA_SIM_LATENCY_MS",
+        "UTA_SIM_JITTER_MS", "UTA_SIM_SEED", "UTA_SIM_REPLAY", "UTA_RECORD",
+        NULL};
+    pid_t cpid;
+    int stat;
+    int ret;
+    int i;
+
+    /* Flush stdout, otherwise buffered output is printed twice */
+    (void)fflush(stdout);
+    cpid = fork();
+    if(cpid < 0)
+    {
+        printf("fork failed\n");
+        return 1;
+    }
+
+    if(cpid == 0)
+    {
+        /* Child process */
+        for(i = 0; sim_env[i]; i++)
+        {
+            (void)unsetenv(sim_env[i]);
+        }
+
+        if(uta.open(uta_context) != UTA_SUCCESS)
+        {
+            printf("ERROR during uta.open!\n");
+            exit(1);
+        }
+
+        ret = test_case(uta_context);
+
+        if(uta.close(uta_context) != UTA_SUCCESS)
+        {
+            printf("ERROR during uta.close!\n");
+            exit(1);
+        }
+        (void)fflush(stdout);
+        exit(ret);
+    }
+
+    /* Wait for the termination of the child process and grep exit code */
+    if((waitpid(cpid, &stat, 0) != cpid) || !WIFEXITED(stat) ||
+        (WEXITSTATUS(stat) != 0))
+    {
+        return 1;
+    }
+    return 0;
+}
+
+/**
+ * @brief Test the fault injection window of the simulator. The calls 3 and 4
+ *      have to fail with the configured return code, a configured return code
+ *      of UTA_SUCCESS has to be replaced by UTA_TA_ERROR and a malformed
+ *      value has to let the call fail.
+ * @param[in,out] uta_context Pointer to the uta_context struct.
+ * @return In case of success the function returns 0, 1 otherwise.
+ */
+static int test_sim_fault_window(uta_context_v1_t *uta_context)
+{
+    const uta_rc expected[] = {UTA_SUCCESS, UTA_SUCCESS, UTA_INVALID_KEY_SLOT,
+        UTA_INVALID_KEY_SLOT, UTA_SUCCESS};
+    uint8_t key[KEYLEN];
+    uta_rc rc;
+    int i;
+
+    printf("Executing %s\n",__FUNCTION__);
+
+    set_sim_fail_call(3);
+    (void)setenv("UTA_SIM_FAIL_COUNT", "2", 1);
+    (void)setenv("UTA_SIM_FAIL_RC", "0x03", 1);
+
+    /* Calls 1 to 5 */
+    for(i = 0; i < 5; i++)
+    {
+        rc = uta.self_test(uta_context);
+        if(rc != expected[i])
+        {
+            printf("Call %d returned %x instead of %x\n", i+1,
+                (unsigned int)rc, (unsigned int)expected[i]);
+            return 1;
+        }
+    }
+
+    /* Call 6: UTA_SUCCESS is not accepted as return code of a failing call */
+    set_sim_fail_call(6);
+    (void)setenv("UTA_SIM_FAIL_COUNT", "1", 1);
+    (void)setenv("UTA_SIM_FAIL_RC", "0", 1);
+    rc = uta.derive_key(uta_context, key, KEYLEN, (const uint8_t *)"failrc=0",
+        DVLEN, 0);
+    if(rc != UTA_TA_ERROR)
+    {
+        printf("UTA_SIM_FAIL_RC=0 returned %x instead of %x\n",
+            (unsigned int)rc, (unsigned int)UTA_TA_ERROR);
+        return 1;
+    }
+
+    /* Call 7: A malformed value must not disable the fault injection */
+    (void)setenv("UTA_SIM_FAIL_CALL", "three", 1);
+    rc = uta.self_test(uta_context);
+    if(rc != UTA_TA_ERROR)
+    {
+        printf("Malformed UTA_SIM_FAIL_CALL returned %x instead of %x\n",
+            (unsigned int)rc, (unsigned int)UTA_TA_ERROR);
+        return 1;
+    }
+
+    return 0;
+}
+
+/**
+ * @brief Test the corrupt mode of the fault injection. The second call of
+ *      derive_key has to succeed, but return the inverted key.
+ * @param[in,out] uta_context Pointer to the uta_context struct.
+ * @return In case of success the function returns 0, 1 otherwise.
+ */
+static int test_sim_fault_corrupt(uta_context_v1_t *uta_context)
+{
+    uint8_t ref_key[KEYLEN];
+    uint8_t key[KEYLEN];
+    uta_rc rc;
+    int i;
+
+    printf("Executing %s\n",__FUNCTION__);
+
+    /* Call 1 */
+    rc = uta.derive_key(uta_context, ref_key, KEYLEN,
+        (const uint8_t *)"corrupt!", DVLEN, 0);
+    if(rc != UTA_SUCCESS)
+    {
+        printf("uta.derive_key failed\n");
+        return 1;
+    }
+
+    /* Call 2 */
+    set_sim_fail_call(2);
+    (void)setenv("UTA_SIM_FAIL_MODE", "corrupt", 1);
+    rc = uta.derive_key(uta_context, key, KEYLEN, (const uint8_t *)"corrupt!",
+        DVLEN, 0);
+    if(rc != UTA_SUCCESS)
+    {
+        printf("uta.derive_key in corrupt mode returned %x\n",
+            (unsigned int)rc);
+        return 1;
+    }
+
+    for(i = 0; i < KEYLEN; i++)
+    {
+        if(key[i] != (uint8_t)(ref_key[i] ^ 0xff))
+        {
+            printf("Key has not been inverted in corrupt mode\n");
+            return 1;
+        }
+    }
+
+    return 0;
+}
+
+/**
+ * @brief Test the latency injection of the simulator. The calls of open,
+ *      close and self_test have to be delayed at least by UTA_SIM_LATENCY_MS
+ *      and a negative jitter has to be rejected instead of crashing.
+ * @param[in,out] uta_context Pointer to the uta_context struct.
+ * @return In case of success the function returns 0, 1 otherwise.
+ */
+static int test_sim_latency(uta_context_v1_t *uta_context)
+{
+    struct timespec start;
+    char latency[16];
+    long duration;
+    uta_rc rc;
+
+    printf("Executing %s\n",__FUNCTION__);
+
+    (void)snprintf(latency, sizeof(latency), "%d", SIM_LATENCY_MS);
+    (void)setenv("UTA_SIM_LATENCY_MS", latency, 1);
+
+    (void)clock_gettime(CLOCK_MONOTONIC, &start);
+    rc = uta.self_test(uta_context);
+    duration = elapsed_ms(&start);
+    if((rc != UTA_SUCCESS) || (duration < SIM_LATENCY_MS))
+    {
+        printf("uta.self_test returned %x after %ld ms\n", (unsigned int)rc,
+            duration);
+        return 1;
+    }
+
+    (void)clock_gettime(CLOCK_MONOTONIC, &start);
+    rc = uta.close(uta_context);
+    if(rc == UTA_SUCCESS)
+    {
+        rc = uta.open(uta_context);
+    }
+    duration = elapsed_ms(&start);
+    if((rc != UTA_SUCCESS) || (duration < 2*SIM_LATENCY_MS))
+    {
+        printf("uta.close/uta.open returned %x after %ld ms\n",
+            (unsigned int)rc, duration);
+        return 1;
+    }
+
+    /* A negative jitter wraps around in strtoul and has to be rejected */
+    (void)setenv("UTA_SIM_LATENCY_MS", "0", 1);
+    (void)setenv("UTA_SIM_JITTER_MS", "-1", 1);
+    rc = uta.self_test(uta_context);
+    if(rc != UTA_TA_ERROR)
+    {
+        printf("UTA_SIM_JITTER_MS=-1 returned %x instead of %x\n",
+            (unsigned int)rc, (unsigned int)UTA_TA_ERROR);
+        return 1;
+    }
+    (void)unsetenv("UTA_SIM_JITTER_MS");
+
+    return 0;
+}
+
+/**
+ * @brief Returns the milliseconds elapsed since start.
+ * @param[in] start Start time measured with CLOCK_MONOTONIC.
+ * @return Elapsed time in milliseconds.
+ */
+static long elapsed_ms(const struct timespec *start)
+{
+    struct timespec now;
+
+    (void)clock_gettime(CLOCK_MONOTONIC, &now);
+    return (long)(now.tv_sec - start->tv_sec) * 1000L +
+        (now.tv_nsec - start->tv_nsec) / 1000000L;
+}
+
+/**
+ * @brief Sets the first failing call of the fault injection. The calls of the
+ *      power-on self-test in the operational gating mode are added.
+ * @param[in] call Number of the first failing call of the test case.
+ */
+static void set_sim_fail_call(unsigned long call)
+{
+    char value[24];
+
+    (void)snprintf(value, sizeof(value), "%lu", call + SIM_POST_CALLS);
+    (void)setenv("UTA_SIM_FAIL_CALL", value, 1);
+}
+
+#ifdef OPERATIONAL_GATING
+/**
+ * @brief Test the operational gating mode. A corrupted known-answer test in
+ *      the power-on self-test has to let uta_init_v1 fail and all further
+ *      calls have to be refused, even if the fault is gone. The test runs in
+ *      a child process, because the error state is latched for the process.
+ * @return In case of success the function returns 0, 1 otherwise.
+ */
+static int test_sim_gating(void)
+{
+    uta_api_v1_t uta_gate;
+    uta_context_v1_t *uta_context;
+    pid_t cpid;
+    int stat;
+
+    printf("Executing %s\n",__FUNCTION__);
+
+    (void)fflush(stdout);
+    cpid = fork();
+    if(cpid < 0)
+    {
+        printf("fork failed\n");
+        return 1;
+    }
+
+    if(cpid == 0)
+    {
+        /* Child process: corrupt the key of the known-answer test (call 2) */
+        (void)setenv("UTA_SIM_FAIL_CALL", "2", 1);
+        (void)setenv("UTA_SIM_FAIL_COUNT", "1", 1);
+        (void)setenv("UTA_SIM_FAIL_MODE", "corrupt", 1);
+        if(uta_init_v1(&uta_gate) != UTA_TA_ERROR)
+        {
+            printf("uta_init_v1 did not detect the failed known-answer test\n");
+            exit(1);
+        }
+        (void)unsetenv("UTA_SIM_FAIL_CALL");
+        (void)unsetenv("UTA_SIM_FAIL_COUNT");
+        (void)unsetenv("UTA_SIM_FAIL_MODE");
+
+        if(uta_init_v1(&uta_gate) != UTA_TA_ERROR)
+        {
+            printf("The error state has not been latched\n");
+            exit(1);
+        }
+
+        uta_context = malloc(uta_gate.context_v1_size());
+        if((uta_context == NULL) ||
+            (uta_gate.open(uta_context) != UTA_TA_ERROR) ||
+            (uta_gate.self_test(uta_context) != UTA_TA_ERROR))
+        {
+            printf("Calls have not been refused in the error state\n");
+            exit(1);
+        }
+        free(uta_context);
+        (void)fflush(stdout);
+        exit(0);
+    }
+
+    if((waitpid(cpid, &stat, 0) != cpid) || !WIFEXITED(stat) ||
+        (WEXITSTATUS(stat) != 0))
+    {
+        return 1;
+    }
+    return 0;
+}
+#endif
+
+/**
+ * @brief Test the seeded RNG of the simulator. Two processes seeded with the
+ *      same value have to produce the same random bytes, a different seed has
+ *      to produce different bytes and a malformed seed has to be rejected.
+ * @param[in,out] uta_context Pointer to the uta_context struct.
+ * @return In case of success the function returns 0, 1 otherwise.
+ */
+static int test_sim_seed(uta_context_v1_t *uta_context)
+{
+    uint8_t random_1[SIM_SEED_LEN];
+    uint8_t random_2[SIM_SEED_LEN];
+    uint8_t random_3[SIM_SEED_LEN];
+    uta_rc rc;
+
+    printf("Executing %s\n",__FUNCTION__);
+
+    if((read_seeded_random(uta_context, "42", random_1, SIM_SEED_LEN) != 0) ||
+        (read_seeded_random(uta_context, "42", random_2, SIM_SEED_LEN) != 0) ||
+        (read_seeded_random(uta_context, "43", random_3, SIM_SEED_LEN) != 0))
+    {
+        printf("Reading seeded random numbers failed\n");
+        return 1;
+    }
+
+    if(memcmp(random_1, random_2, SIM_SEED_LEN) != 0)
+    {
+        printf("Same seed produced different random numbers\n");
+        return 1;
+    }
+
+    if(memcmp(random_1, random_3, SIM_SEED_LEN) == 0)
+    {
+        printf("Different seeds produced the same random numbers\n");
+        return 1;
+    }
+
+    (void)setenv("UTA_SIM_SEED", "forty-two", 1);
+    rc = uta.open(uta_context);
+    (void)unsetenv("UTA_SIM_SEED");
+    if(rc != UTA_TA_ERROR)
+    {
+        printf("Malformed UTA_SIM_SEED returned %x instead of %x\n",
+            (unsigned int)rc, (unsigned int)UTA_TA_ERROR);
+        return 1;
+    }
+
+    return 0;
+}
+
+/**
+ * @brief Reads random bytes in a separate process, which seeds the simulator
+ *      with UTA_SIM_SEED during its open call.
+ * @param[in,out] uta_context Pointer to the uta_context struct.
+ * @param[in] seed Value of UTA_SIM_SEED.
+ * @param[out] random Buffer for the random bytes.
+ * @param[in] len_random Number of random bytes.
+ * @return In case of success the function returns 0, 1 otherwise.
+ */
+static int read_seeded_random(uta_context_v1_t *uta_context, const char *seed,
+    uint8_t *random, size_t len_random)
+{
+    int pipefd[2];
+    pid_t cpid;
+    int stat;
+    ssize_t len;
+
+    if(pipe(pipefd) != 0)
+    {
+        return 1;
+    }
+
+    (void)fflush(stdout);
+    cpid = fork();
+    if(cpid < 0)
+    {
+        (void)close(pipefd[0]);
+        (void)close(pipefd[1]);
+        return 1;
+    }
+
+    if(cpid == 0)
+    {
+        /* Child process */
+        (void)close(pipefd[0]);
+        (void)setenv("UTA_SIM_SEED", seed, 1);
+        if((uta.open(uta_context) != UTA_SUCCESS) ||
+            (uta.get_random(uta_context, random, len_random) != UTA_SUCCESS) ||
+            (uta.close(uta_context) != UTA_SUCCESS) ||
+            (write(pipefd[1], random, len_random) != (ssize_t)len_random))
+        {
+            exit(1);
+        }
+        exit(0);
+    }
+
+    (void)close(pipefd[1]);
+    len = read(pipefd[0], random, len_random);
+    (void)close(pipefd[0]);
+
+    if((waitpid(cpid, &stat, 0) != cpid) || !WIFEXITED(stat) ||
+        (WEXITSTATUS(stat) != 0) || (len != (ssize_t)len_random))
+    {
+        return 1;
+    }
+    return 0;
+}
+
+/**
+ * @brief Writes a string to a new temporary file.
+ * @param[in,out] path Template for mkstemp, replaced by the file name.
+ * @param[in] content String to be written to the file.
+ * @return In case of success the function returns 0, 1 otherwise.
+ */
+static int write_temp_file(char *path, const char *content)
+{
+    int fd;
+    ssize_t len = (ssize_t)strlen(content);
+
+    fd = mkstemp(path);
+    if(fd < 0)
+    {
+        return 1;
+    }
+
+    if(write(fd, content, len) != len)
+    {
+        (void)close(fd);
+        (void)unlink(path);
+        return 1;
+    }
+    (void)close(fd);
+
+    return 0;
+}
+
+/**
+ * @brief Test the replay of a cassette by the simulator. The recorded return
+ *      codes and outputs have to be returned, a call not matching the next
+ *      entry has to fail without consuming it and a call after the end of the
+ *      cassette has to fail.
+ * @param[in,out] uta_context Pointer to the uta_context struct.
+ * @return In case of success the function returns 0, 1 otherwise.
+ */
+static int test_sim_replay(uta_context_v1_t *uta_context)
+{
+    const uint8_t ref_key[] = {0xde, 0xad, 0xbe, 0xef};
+    const uint8_t ref_random[] = {0x01, 0x02, 0x03, 0x04};
+    char path[] = "/tmp/uta_cassette_XXXXXX";
+    uint8_t buffer[4];
+    uint8_t uuid[16];
+    int ret = 1;
+
+    printf("Executing %s\n",__FUNCTION__);
+
+    if(write_temp_file(path, SIM_CASSETTE) != 0)
+    {
+        printf("Writing the cassette failed\n");
+        return 1;
+    }
+    (void)setenv("UTA_SIM_REPLAY", path, 1);
+
+    if(uta.open(uta_context) != UTA_SUCCESS)
+    {
+        printf("Replay of uta.open failed\n");
+    }
+    else if((uta.derive_key(uta_context, buffer, 4,
+        (const uint8_t *)"replay!!", DVLEN, 0) != UTA_SUCCESS) ||
+        (memcmp(buffer, ref_key, 4) != 0))
+    {
+        printf("Replay of uta.derive_key failed\n");
+    }
+    else if((uta.get_random(uta_context, buffer, 4) != UTA_SUCCESS) ||
+        (memcmp(buffer, ref_random, 4) != 0))
+    {
+        printf("Replay of uta.get_random failed\n");
+    }
+    else if(uta.get_device_uuid(uta_context, uuid) != UTA_TA_ERROR)
+    {
+        printf("Replay of the recorded error of uta.get_device_uuid failed\n");
+    }
+    else if(uta.get_random(uta_context, buffer, 4) != UTA_TA_ERROR)
+    {
+        printf("Call not matching the cassette did not fail\n");
+    }
+    else if(uta.self_test(uta_context) != UTA_SUCCESS)
+    {
+        printf("Replay of uta.self_test after a non-matching call failed\n");
+    }
+    else if(uta.close(uta_context) != UTA_SUCCESS)
+    {
+        printf("Replay of uta.close failed\n");
+    }
+    else if(uta.self_test(uta_context) != UTA_TA_ERROR)
+    {
+        printf("Call after the end of the cassette did not fail\n");
+    }
+    else
+    {
+        ret = 0;
+    }
+
+    (void)unsetenv("UTA_SIM_REPLAY");
+    (void)unlink(path);
+
+    /* The harness closes the context again */
+    (void)uta.open(uta_context);
+
+    return ret;
+}
+
+#ifdef RECORDING
+/**
+ * @brief Test the recording of calls and their replay by the simulator. The
+ *      replayed calls have to return the recorded outputs.
+ * @param[in,out] uta_context Pointer to the uta_context struct.
+ * @return In case of success the function returns 0, 1 otherwise.
+ */
+static int test_sim_record(uta_context_v1_t *uta_context)
+{
+    char path[] = "/tmp/uta_cassette_XXXXXX";
+    uta_api_v1_t uta_rec;
+    uint8_t ref_key[KEYLEN];
+    uint8_t ref_random[KEYLEN];
+    uint8_t key[KEYLEN];
+    uint8_t random[KEYLEN];
+    int ret = 1;
+
+    printf("Executing %s\n",__FUNCTION__);
+
+    if(write_temp_file(path, "") != 0)
+    {
+        printf("Creating the cassette failed\n");
+        return 1;
+    }
+
+    /* Record the calls */
+    (void)setenv("UTA_RECORD", path, 1);
+    if((uta_init_v1(&uta_rec) != UTA_SUCCESS) ||
+        (uta_rec.open(uta_context) != UTA_SUCCESS) ||
+        (uta_rec.derive_key(uta_context, ref_key, KEYLEN,
+        (const uint8_t *)"record!!", DVLEN, 1) != UTA_SUCCESS) ||
+        (uta_rec.get_random(uta_context, ref_random, KEYLEN) != UTA_SUCCESS) ||
+        (uta_rec.close(uta_context) != UTA_SUCCESS))
+    {
+        printf("Recording failed\n");
+        (void)unlink(path);
+        return 1;
+    }
+    (void)unsetenv("UTA_RECORD");
+
+    /* Replay the recorded calls */
+    (void)setenv("UTA_SIM_REPLAY", path, 1);
+    if((uta.open(uta_context) != UTA_SUCCESS) ||
+        (uta.derive_key(uta_context, key, KEYLEN,
+        (const uint8_t *)"record!!", DVLEN, 1) != UTA_SUCCESS) ||
+        (uta.get_random(uta_context, random, KEYLEN) != UTA_SUCCESS) ||
+        (uta.close(uta_context) != UTA_SUCCESS))
+    {
+        printf("Replay of the recorded calls failed\n");
+    }
+    else if((memcmp(key, ref_key, KEYLEN) != 0) ||
+        (memcmp(random, ref_random, KEYLEN) != 0))
+    {
+        printf("Replayed outputs differ from the recorded outputs\n");
+    }
+    else
+    {
+        ret = 0;
+    }
+
+    (void)unsetenv("UTA_SIM_REPLAY");
+    (void)unlink(path);
+
+    /* The harness closes the context again */
+    (void)uta.open(uta_context);
+
+    return ret;
+}
+#endif
+#endif
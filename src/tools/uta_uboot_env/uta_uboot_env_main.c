@@ -0,0 +1,509 @@
+/** @file uta_uboot_env_main.c
+*
+* @brief Compute and verify an HMAC over a U-Boot environment with a key
+* derived from the TA
+*
+* @copyright Copyright (c) Siemens Mobility GmbH, 2020
+*
+* @license This work is licensed under the terms of the Apache Software License
+* 2.0. See the COPYING file in the top-level directory.
+*
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+/*******************************************************************************
+ * Includes
+ ******************************************************************************/
+#include <unistd.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <errno.h>
+
+#include <uta.h>
+#include <mbedtls/md.h>
+#include <mbedtls/platform_util.h>
+
+/*******************************************************************************
+ * Defines
+ ******************************************************************************/
+#define TA_KEY_BYTES     32
+#define HMAC_BYTES       32
+
+/* Size of the CRC32 in front of the environment data */
+#define ENV_CRC_BYTES    4
+
+/* Size of the flags byte of a redundant environment (CONFIG_SYS_REDUNDAND_ENVIRONMENT) */
+#define ENV_FLAGS_BYTES  1
+
+/* Upper limit for the environment size if it is not given on the command line */
+#define ENV_SIZE_MAX     (1024 * 1024)
+
+/*******************************************************************************
+ * Enums
+ ******************************************************************************/
+typedef enum {NO_COMMAND, SEAL_COMMAND, VERIFY_COMMAND} env_command_t;
+
+/*******************************************************************************
+ * Private function prototypes
+ ******************************************************************************/
+static int bytes2hexstr(const unsigned char *bin_in, size_t input_length,
+                        char *hex_out, size_t output_length);
+static int hexstr2bytes(const char *hex_in, unsigned char *bin_out,
+                        size_t output_length);
+static int read_env(const char *env_file, size_t env_size,
+                    unsigned char **env, size_t *len_env);
+static int compute_env_hmac(const unsigned char *env, size_t len_env,
+                            int redundant, const char *derivation_string,
+                            uint8_t key_slot, unsigned char *hmac);
+static int write_hmac_file(const char *hmac_file, const unsigned char *hmac);
+static int read_hmac_file(const char *hmac_file, unsigned char *hmac);
+static int compare_hmac(const unsigned char *a, const unsigned char *b);
+
+/*******************************************************************************
+ * Private function bodies
+ ******************************************************************************/
+
+/**
+ * @brief Converts bytes to a string with hexadecimal values.
+ * @param[in] bin_in Buffer with the input bytes.
+ * @param[in] input_length Number of bytes to convert.
+ * @param[out] hex_out Buffer with the string output.
+ * @param[in] output_length Size of output buffer 'hex_out'.
+ * @return returns 0 on success,
+ *         returns 1 in case of insufficient output buffer length.
+ */
+static int bytes2hexstr(const unsigned char *bin_in, size_t input_length,
+                        char *hex_out, size_t output_length)
+{
+    const char hex_str[] = "0123456789abcdef";
+
+    if (output_length < (input_length*2)+1)
+    {
+        return 1;
+    }
+
+    for (size_t i=0; i < input_length; i++)
+    {
+        hex_out[i*2]   = hex_str[(bin_in[i]>>4) & 0xf];
+        hex_out[i*2+1] = hex_str[ bin_in[i]     & 0xf];
+    }
+    hex_out[input_length*2] = '\0';
+
+    return 0;
+}
+
+/**
+ * @brief Converts a string with hexadecimal values to bytes.
+ * @param[in] hex_in String with exactly 2*output_length hex characters.
+ * @param[out] bin_out Buffer for the output bytes.
+ * @param[in] output_length Number of bytes to convert.
+ * @return returns 0 on success,
+ *         returns 1 if the string is not valid.
+ */
+static int hexstr2bytes(const char *hex_in, unsigned char *bin_out,
+                        size_t output_length)
+{
+    const char hex_str[] = "0123456789abcdef";
+    const char *high;
+    const char *low;
+
+    for (size_t i=0; i < output_length; i++)
+    {
+        if ((hex_in[i*2] == '\0') || (hex_in[i*2+1] == '\0'))
+        {
+            return 1;
+        }
+        high = strchr(hex_str, hex_in[i*2]);
+        low = strchr(hex_str, hex_in[i*2+1]);
+        if ((NULL == high) || (NULL == low))
+        {
+            return 1;
+        }
+        bin_out[i] = (unsigned char)(((high - hex_str) << 4) | (low - hex_str));
+    }
+
+    return 0;
+}
+
+/**
+ * @brief Reads the environment from a file or device.
+ * @param[in] env_file Path of the environment image (e.g. /dev/mtd1).
+ * @param[in] env_size Size of the environment, 0 to read until EOF.
+ * @param[out] env Buffer with the environment, allocated by this function.
+ * @param[out] len_env Number of bytes read.
+ * @return returns 0 on success,
+ *         returns 1 in case of an error
+ */
+static int read_env(const char *env_file, size_t env_size,
+                    unsigned char **env, size_t *len_env)
+{
+    FILE *fp;
+    size_t len_max = (0 == env_size) ? ENV_SIZE_MAX : env_size;
+
+    fp = fopen(env_file, "rb");
+    if (NULL == fp)
+    {
+        fprintf(stderr, "ERROR: Cannot open %s: %s\n", env_file, strerror(errno));
+        return 1;
+    }
+
+    /* Allocate one additional byte to detect an environment exceeding
+     * ENV_SIZE_MAX */
+    *env = malloc(len_max + 1);
+    if (NULL == *env)
+    {
+        (void)fclose(fp);
+        return 1;
+    }
+
+    *len_env = fread(*env, 1, len_max + 1, fp);
+    (void)fclose(fp);
+
+    if (0 == env_size)
+    {
+        if (*len_env > ENV_SIZE_MAX)
+        {
+            fprintf(stderr, "ERROR: Environment exceeds %d bytes, specify its size with -l\n", ENV_SIZE_MAX);
+            free(*env);
+            return 1;
+        }
+    }
+    else
+    {
+        if (*len_env < env_size)
+        {
+            fprintf(stderr, "ERROR: %s is shorter than %zu bytes\n", env_file, env_size);
+            free(*env);
+            return 1;
+        }
+        *len_env = env_size;
+    }
+
+    return 0;
+}
+
+/**
+ * @brief Computes the HMAC of the environment with a key from the TA. The HMAC
+ *        covers the CRC32 and the complete data area, but not the flags byte
+ *        of a redundant environment, which changes with every write.
+ * @param[in] env Buffer with the environment.
+ * @param[in] len_env Length of the environment.
+ * @param[in] redundant 1 if the environment contains a flags byte.
+ * @param[in] derivation_string Derivation value of the HMAC key.
+ * @param[in] key_slot Key slot used for the derivation.
+ * @param[out] hmac Buffer of HMAC_BYTES for the HMAC.
+ * @return returns 0 on success,
+ *         returns 1 in case of an error
+ */
+static int compute_env_hmac(const unsigned char *env, size_t len_env,
+                            int redundant, const char *derivation_string,
+                            uint8_t key_slot, unsigned char *hmac)
+{
+    uta_rc rc;
+    uta_api_v1_t uta;
+    uta_context_v1_t *uta_context;
+    const mbedtls_md_info_t *sha256_hmac;
+    mbedtls_md_context_t md_ctx;
+    unsigned char key[TA_KEY_BYTES] = {0};
+    char dv_padded[UTA_LEN_DV_V1];
+    size_t len_header = ENV_CRC_BYTES + (redundant ? ENV_FLAGS_BYTES : 0);
+    int ret;
+
+    if (len_env <= len_header)
+    {
+        fprintf(stderr, "ERROR: Environment is too short\n");
+        return 1;
+    }
+
+    /* Same padding of the derivation value as in uta_get_passphrase */
+    int padding = 0;
+    for(int i=0; i < UTA_LEN_DV_V1; i++)
+    {
+        if ((0 == padding) && (0 == derivation_string[i]))
+        {
+           padding=1;
+        }
+        dv_padded[i] = padding==1 ? '=' : derivation_string[i];
+    }
+
+    rc = uta_init_v1(&uta);
+    if (UTA_SUCCESS != rc)
+    {
+        return 1;
+    }
+
+    uta_context = malloc(uta.context_v1_size());
+    if (NULL == uta_context)
+    {
+        return 1;
+    }
+
+    rc = uta.open(uta_context);
+    if (UTA_SUCCESS != rc)
+    {
+        free(uta_context);
+        return 1;
+    }
+
+    rc = uta.derive_key(uta_context, key, TA_KEY_BYTES,
+                        (unsigned char *) dv_padded, UTA_LEN_DV_V1, key_slot);
+    (void)uta.close(uta_context);
+    free(uta_context);
+    if (UTA_SUCCESS != rc)
+    {
+        return 1;
+    }
+
+    sha256_hmac = mbedtls_md_info_from_type(MBEDTLS_MD_SHA256);
+    mbedtls_md_init(&md_ctx);
+    ret = mbedtls_md_setup(&md_ctx, sha256_hmac, 1);
+    if (0 == ret)
+    {
+        ret = mbedtls_md_hmac_starts(&md_ctx, key, TA_KEY_BYTES);
+    }
+    if (0 == ret)
+    {
+        ret = mbedtls_md_hmac_update(&md_ctx, env, ENV_CRC_BYTES);
+    }
+    if (0 == ret)
+    {
+        ret = mbedtls_md_hmac_update(&md_ctx, env + len_header,
+                                     len_env - len_header);
+    }
+    if (0 == ret)
+    {
+        ret = mbedtls_md_hmac_finish(&md_ctx, hmac);
+    }
+    mbedtls_md_free(&md_ctx);
+    mbedtls_platform_zeroize(key, sizeof(key));
+
+    return (0 == ret) ? 0 : 1;
+}
+
+/**
+ * @brief Writes the HMAC as hex string to a file.
+ * @param[in] hmac_file Path of the HMAC file.
+ * @param[in] hmac Buffer of HMAC_BYTES with the HMAC.
+ * @return returns 0 on success,
+ *         returns 1 in case of an error
+ */
+static int write_hmac_file(const char *hmac_file, const unsigned char *hmac)
+{
+    FILE *fp;
+    char hex[HMAC_BYTES * 2 + 1];
+
+    (void)bytes2hexstr(hmac, HMAC_BYTES, hex, sizeof(hex));
+
+    fp = fopen(hmac_file, "w");
+    if (NULL == fp)
+    {
+        fprintf(stderr, "ERROR: Cannot open %s: %s\n", hmac_file, strerror(errno));
+        return 1;
+    }
+    if (fprintf(fp, "%s\n", hex) < 0)
+    {
+        (void)fclose(fp);
+        return 1;
+    }
+    if (0 != fclose(fp))
+    {
+        return 1;
+    }
+
+    return 0;
+}
+
+/**
+ * @brief Reads the HMAC from a file written by write_hmac_file.
+ * @param[in] hmac_file Path of the HMAC file.
+ * @param[out] hmac Buffer of HMAC_BYTES for the HMAC.
+ * @return returns 0 on success,
+ *         returns 1 in case of an error
+ */
+static int read_hmac_file(const char *hmac_file, unsigned char *hmac)
+{
+    FILE *fp;
+    char hex[HMAC_BYTES * 2 + 2] = {0};
+
+    fp = fopen(hmac_file, "r");
+    if (NULL == fp)
+    {
+        fprintf(stderr, "ERROR: Cannot open %s: %s\n", hmac_file, strerror(errno));
+        return 1;
+    }
+    if (NULL == fgets(hex, sizeof(hex), fp))
+    {
+        (void)fclose(fp);
+        fprintf(stderr, "ERROR: Cannot read %s\n", hmac_file);
+        return 1;
+    }
+    (void)fclose(fp);
+
+    if ((HMAC_BYTES * 2 != strcspn(hex, "\n")) || (0 != hexstr2bytes(hex, hmac, HMAC_BYTES)))
+    {
+        fprintf(stderr, "ERROR: %s does not contain a valid HMAC\n", hmac_file);
+        return 1;
+    }
+
+    return 0;
+}
+
+/**
+ * @brief Compares two HMACs in constant time.
+ * @param[in] a First HMAC of HMAC_BYTES.
+ * @param[in] b Second HMAC of HMAC_BYTES.
+ * @return returns 0 if the HMACs are equal,
+ *         returns 1 otherwise
+ */
+static int compare_hmac(const unsigned char *a, const unsigned char *b)
+{
+    unsigned char diff = 0;
+
+    for (int i=0; i < HMAC_BYTES; i++)
+    {
+        diff |= a[i] ^ b[i];
+    }
+
+    return (0 == diff) ? 0 : 1;
+}
+
+/**
+ * @brief Command line interface to seal a U-Boot environment with an HMAC
+ *        derived from the HW trust anchor and to verify it.
+ * @return exit status 0 on success,
+ *         exit status 1 in case of an error,
+ *         exit status 2 if the verification failed
+ */
+int main(int argc, char *argv[])
+{
+   env_command_t command = NO_COMMAND;
+   char *env_file = NULL;
+   char *hmac_file = NULL;
+   char *dval = "ubootenv";
+   char *lval = NULL;
+   char *endptr;
+   int redundant = 0;
+   int key_slot = 1;
+   size_t env_size = 0;
+   unsigned char *env = NULL;
+   size_t len_env = 0;
+   unsigned char hmac[HMAC_BYTES];
+   unsigned char hmac_expected[HMAC_BYTES];
+   int ret;
+   int c;
+
+   while ((c = getopt (argc, argv, "svf:m:l:rd:k:h")) != -1)
+   {
+       switch(c)
+       {
+       case 's':
+          command = SEAL_COMMAND;
+          break;
+       case 'v':
+          command = VERIFY_COMMAND;
+          break;
+       case 'f':
+          env_file = optarg;
+          break;
+       case 'm':
+          hmac_file = optarg;
+          break;
+       case 'l':
+          lval = optarg;
+          break;
+       case 'r':
+          redundant = 1;
+          break;
+       case 'd':
+          dval = optarg;
+          break;
+       case 'k':
+          if (0 == strcmp(optarg, "0"))
+          {
+             key_slot = 0;
+          }
+          else if (0 == strcmp(optarg, "1"))
+          {
+             key_slot = 1;
+          }
+          else
+          {
+             fprintf(stderr, "ERROR: Wrong key_slot, specify either 0 or 1\n");
+             return 1;
+          }
+          break;
+       case '?':
+       case 'h':
+          fprintf(stderr, "### Protect the U-Boot environment with the UTA trust anchor ### \n\n");
+          fprintf(stderr, "Usage: uta_uboot_env -s|-v -f <env_file> -m <hmac_file> [-l <env_size>] [-r] [-d <derivation_string>] [-k <key_slot>] [-h]\n\n");
+          fprintf(stderr, "-s: seal the environment, (re-)write the HMAC to <hmac_file>\n");
+          fprintf(stderr, "-v: verify the environment against the HMAC in <hmac_file>\n");
+          fprintf(stderr, "-f <env_file>: environment image or device (e.g. /dev/mtd1)\n");
+          fprintf(stderr, "-m <hmac_file>: file containing the HMAC as hex string\n");
+          fprintf(stderr, "-l <env_size>: size of the environment (CONFIG_ENV_SIZE);\n");
+          fprintf(stderr, "   (default: size of <env_file>)\n");
+          fprintf(stderr, "-r: redundant environment with flags byte\n");
+          fprintf(stderr, "-d <derivation_string>: string used in the derivation of the HMAC key,\n");
+          fprintf(stderr, "   maximum length is %d characters; (default value: 'ubootenv')\n", UTA_LEN_DV_V1);
+          fprintf(stderr, "-k <key_slot>: select key_slot from 0 and 1;\n");
+          fprintf(stderr, "   (default: 1, key_slot containing device specific key)\n");
+          fprintf(stderr, "-h This help message\n");
+          return 1;
+       }
+   }
+
+   if ((NO_COMMAND == command) || (NULL == env_file) || (NULL == hmac_file))
+   {
+      fprintf(stderr, "ERROR: Specify -s or -v, -f <env_file> and -m <hmac_file>\n");
+      return 1;
+   }
+
+   if (UTA_LEN_DV_V1 < strnlen(dval, UTA_LEN_DV_V1+1))
+   {
+      fprintf(stderr, "ERROR: Derivation string must be %d or less characters long\n", UTA_LEN_DV_V1);
+      return 1;
+   }
+
+   if (NULL != lval)
+   {
+      errno = 0;
+      env_size = strtoul(lval, &endptr, 0);
+      if ((0 != errno) || (endptr == lval) || ('\0' != *endptr) ||
+          ('-' == lval[0]) || (0 == env_size))
+      {
+         fprintf(stderr, "ERROR: Invalid environment size '%s'\n", lval);
+         return 1;
+      }
+   }
+
+   if (0 != read_env(env_file, env_size, &env, &len_env))
+   {
+      return 1;
+   }
+
+   ret = compute_env_hmac(env, len_env, redundant, dval, key_slot, hmac);
+   free(env);
+   if (0 != ret)
+   {
+      fprintf(stderr, "ERROR: Computation of the HMAC failed\n");
+      return 1;
+   }
+
+   if (SEAL_COMMAND == command)
+   {
+      return write_hmac_file(hmac_file, hmac);
+   }
+
+   if (0 != read_hmac_file(hmac_file, hmac_expected))
+   {
+      return 1;
+   }
+   if (0 != compare_hmac(hmac, hmac_expected))
+   {
+      fprintf(stderr, "ERROR: HMAC of the environment does not match\n");
+      return 2;
+   }
+
+   return 0;
+}